@@ -0,0 +1,196 @@
+use std::path::{Path, PathBuf};
+
+use log::debug;
+
+use crate::error::{Error, Result};
+use crate::facts::Facts;
+use crate::filesystem::Filesystem;
+
+/// Separator between the base name of a target and the conditions of an alternate.
+const SEPARATOR: &str = "##";
+
+/// A workspace file such as `gitconfig##os.linux` chosen in place of the base target.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Alternate {
+    pub path: PathBuf,
+    pub reason: String,
+}
+
+/// Pick the alternate of `target` that matches the facts best.
+///
+/// Every condition of an alternate must match for it to be considered. The alternate with the
+/// highest score wins, where `hostname` scores 16, `user` 8, `distro` 4, `os` 2, `arch` 1 and
+/// `default` 0. Ties are broken by file name.
+pub fn select<P: AsRef<Path>>(filesystem: &dyn Filesystem, target: P, facts: &Facts) -> Result<Option<Alternate>> {
+    let target = target.as_ref();
+    let (parent, base_name) = match (target.parent(), target.file_name().and_then(|name| name.to_str())) {
+        (Some(parent), Some(base_name)) if !base_name.contains(SEPARATOR) => (parent, base_name),
+        _ => return Ok(None),
+    };
+    if !filesystem.is_dir(parent) {
+        return Ok(None);
+    }
+    let prefix = format!("{}{}", base_name, SEPARATOR);
+    let entries = filesystem
+        .read_dir(parent)
+        .map_err(|e| Error::new("Failed to read workspace directory.").with_inner_error(&e))?;
+    let mut best: Option<(u32, String, Alternate)> = None;
+    for entry in entries {
+        let file_name = entry.file_name().unwrap().to_string_lossy().to_string();
+        let conditions = match file_name.strip_prefix(&prefix) {
+            Some(conditions) => conditions,
+            None => continue,
+        };
+        if let Some(score) = score(conditions, facts) {
+            let better = match best {
+                Some((best_score, ref best_name, _)) => score > best_score || (score == best_score && file_name < *best_name),
+                None => true,
+            };
+            if better {
+                let reason = if conditions == "default" {
+                    "no alternate matched, using default".to_string()
+                } else {
+                    format!("matched {}", conditions)
+                };
+                best = Some((
+                    score,
+                    file_name.clone(),
+                    Alternate {
+                        path: entry,
+                        reason,
+                    },
+                ));
+            }
+        }
+    }
+    return Ok(best.map(|(_, _, alternate)| alternate));
+}
+
+/// `path` up to its first file name with alternate conditions, without them, e.g. `home/.gitconfig`
+/// for `home/.gitconfig##os.linux` or `home/.config/nvim` for `home/.config/nvim##default/init.lua`.
+pub fn base_path<P: AsRef<Path>>(path: P) -> PathBuf {
+    let mut result = PathBuf::new();
+    for component in path.as_ref().components() {
+        let name = component.as_os_str().to_string_lossy();
+        match name.find(SEPARATOR) {
+            Some(index) if index > 0 => {
+                result.push(&name[..index]);
+                break;
+            }
+            _ => result.push(component),
+        }
+    }
+    return result;
+}
+
+/// Whether `target` has alternates in its directory, whether they match or not.
+pub fn has_alternates<P: AsRef<Path>>(filesystem: &dyn Filesystem, target: P) -> bool {
+    let target = target.as_ref();
+    let (parent, base_name) = match (target.parent(), target.file_name().and_then(|name| name.to_str())) {
+        (Some(parent), Some(base_name)) => (parent, base_name),
+        _ => return false,
+    };
+    let prefix = format!("{}{}", base_name, SEPARATOR);
+    return filesystem
+        .read_dir(parent)
+        .map(|entries| {
+            entries
+                .iter()
+                .any(|entry| entry.file_name().is_some_and(|name| name.to_string_lossy().starts_with(&prefix)))
+        })
+        .unwrap_or_default();
+}
+
+fn score(conditions: &str, facts: &Facts) -> Option<u32> {
+    let mut total = 0;
+    for condition in conditions.split(',') {
+        if condition == "default" {
+            continue;
+        }
+        let mut parts = condition.splitn(2, '.');
+        let key = parts.next().unwrap_or("");
+        let value = parts.next().unwrap_or("");
+        let (fact, weight) = match key {
+            "hostname" | "h" => ("hostname", 16),
+            "user" | "u" => ("username", 8),
+            "distro" | "d" => ("distro_id", 4),
+            "os" | "o" => ("os", 2),
+            "arch" | "a" => ("arch", 1),
+            _ => {
+                debug!("Unknown alternate condition `{}`, ignore the alternate.", condition);
+                return None;
+            }
+        };
+        if value.is_empty() || !facts.get(fact).unwrap_or("").eq_ignore_ascii_case(value) {
+            return None;
+        }
+        total += weight;
+    }
+    return Some(total);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filesystem::MemoryFilesystem;
+
+    fn facts() -> Facts {
+        let mut facts = Facts::new();
+        facts.insert("hostname", "laptop");
+        facts.insert("username", "me");
+        facts.insert("distro_id", "arch");
+        facts.insert("os", "linux");
+        facts.insert("arch", "x86_64");
+        return facts;
+    }
+
+    fn selected(files: &[&str]) -> Option<String> {
+        let filesystem = MemoryFilesystem::new();
+        for file in files {
+            filesystem.add_file(Path::new("/dotfiles").join(file), b"").unwrap();
+        }
+        let alternate = select(&filesystem, "/dotfiles/gitconfig", &facts()).unwrap();
+        return alternate.map(|alternate| alternate.path.file_name().unwrap().to_string_lossy().to_string());
+    }
+
+    #[test]
+    fn score_adds_weights_of_matching_conditions() {
+        assert_eq!(score("default", &facts()), Some(0));
+        assert_eq!(score("os.linux", &facts()), Some(2));
+        assert_eq!(score("hostname.laptop,os.linux", &facts()), Some(18));
+        assert_eq!(score("u.me,d.arch,a.x86_64", &facts()), Some(13));
+        assert_eq!(score("os.Linux", &facts()), Some(2));
+    }
+
+    #[test]
+    fn score_rejects_unmatched_and_unknown_conditions() {
+        assert_eq!(score("os.darwin", &facts()), None);
+        assert_eq!(score("os.linux,hostname.desktop", &facts()), None);
+        assert_eq!(score("os.", &facts()), None);
+        assert_eq!(score("shell.zsh", &facts()), None);
+    }
+
+    #[test]
+    fn select_prefers_the_highest_score() {
+        let files = ["gitconfig##default", "gitconfig##os.linux", "gitconfig##hostname.laptop", "gitconfig##os.darwin"];
+        assert_eq!(selected(&files), Some("gitconfig##hostname.laptop".to_string()));
+        assert_eq!(selected(&files[..2]), Some("gitconfig##os.linux".to_string()));
+        assert_eq!(selected(&files[..1]), Some("gitconfig##default".to_string()));
+        assert_eq!(selected(&files[3..]), None);
+        assert_eq!(selected(&["gitconfig"]), None);
+        assert_eq!(selected(&["gitconfig##u.me,d.arch,o.linux,a.x86_64", "gitconfig##h.laptop"]), Some("gitconfig##h.laptop".to_string()));
+    }
+
+    #[test]
+    fn select_breaks_ties_by_file_name() {
+        assert_eq!(selected(&["gitconfig##os.linux", "gitconfig##o.linux"]), Some("gitconfig##o.linux".to_string()));
+        assert_eq!(selected(&["gitconfig##u.me,os.linux", "gitconfig##os.linux,user.me"]), Some("gitconfig##os.linux,user.me".to_string()));
+    }
+
+    #[test]
+    fn base_path_strips_conditions() {
+        assert_eq!(base_path("home/.gitconfig##os.linux"), PathBuf::from("home/.gitconfig"));
+        assert_eq!(base_path("home/.config/nvim##default/init.lua"), PathBuf::from("home/.config/nvim"));
+        assert_eq!(base_path("home/##notes"), PathBuf::from("home/##notes"));
+    }
+}
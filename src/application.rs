@@ -1,15 +1,25 @@
 extern crate clap;
 
-use clap::{App, Arg, ArgMatches, SubCommand};
+use std::env;
+use std::path::PathBuf;
+
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+
+use crate::plugin;
 
 pub fn get_matches() -> ArgMatches<'static> {
+    let plugins = plugin::discover();
+    let help = plugin::help(&plugins).unwrap_or_default();
     return App::new("idot")
         .version("0.1.0")
         .version_short("v")
         .author("bright")
         .about("Simple dotfiles manager")
+        .setting(AppSettings::AllowExternalSubcommands)
+        .after_help(help.as_str())
         .arg(Arg::with_name("debug").long("debug").short("d").help("Show debug information").global(true))
         .arg(Arg::with_name("simulate").long("simulate").short("s").help("Don't make any filesystem changes").global(true))
+        .arg(Arg::with_name("fact").long("fact").takes_value(true).multiple(true).number_of_values(1).help("Override a fact with `key=value`").global(true))
         .arg(Arg::with_name("workspace").takes_value(true).default_value(".").help("The directory that stored dotfiles").global(true))
         .subcommand(SubCommand::with_name("status")
             .version_short("v")
@@ -24,5 +34,62 @@ pub fn get_matches() -> ArgMatches<'static> {
             .version_short("v")
             .display_order(3)
             .about("Delete symbolic links by configuration"))
-        .get_matches();
-}
\ No newline at end of file
+        .subcommand(SubCommand::with_name("clean")
+            .version_short("v")
+            .display_order(4)
+            .about("Delete symbolic links into the workspace that are no longer configured"))
+        .subcommand(SubCommand::with_name("adopt")
+            .version_short("v")
+            .display_order(5)
+            .about("Move a file at a link path into the workspace and link it back")
+            .arg(Arg::with_name("path").required(true).help("The link path of the file"))
+            .arg(Arg::with_name("force").long("force").short("f").help("Overwrite the target in the workspace")))
+        .subcommand(SubCommand::with_name("mv")
+            .version_short("v")
+            .display_order(6)
+            .about("Rename a target in the workspace and move its links along")
+            .arg(Arg::with_name("from").required(true).help("The target in the workspace"))
+            .arg(Arg::with_name("to").required(true).help("The new target in the workspace")))
+        .subcommand(SubCommand::with_name("run")
+            .version_short("v")
+            .display_order(7)
+            .about("Run the pending tasks")
+            .arg(Arg::with_name("task").help("Only run this task and the tasks it depends on")))
+        .subcommand(SubCommand::with_name("allow")
+            .version_short("v")
+            .display_order(8)
+            .about("Review and trust the commands of the workspace"))
+        .subcommand(SubCommand::with_name("render")
+            .version_short("v")
+            .display_order(9)
+            .about("Preview a template rendered with variables and facts")
+            .arg(Arg::with_name("target").required(true).help("The template in the workspace")))
+        .subcommand(SubCommand::with_name("facts")
+            .version_short("v")
+            .display_order(10)
+            .about("Show facts about the current machine")
+            .arg(Arg::with_name("format").long("format").takes_value(true).possible_values(&["text", "json"]).default_value("text").help("Output format")))
+        .get_matches_from(arguments(&plugins));
+}
+/// The command line arguments. clap takes the first free argument for the workspace, so the
+/// default workspace is put before a plugin name, `idot <plugin>` as `idot . <plugin>`.
+fn arguments(plugins: &[(String, PathBuf)]) -> Vec<String> {
+    let mut arguments: Vec<String> = env::args().collect();
+    let mut index = 1;
+    while index < arguments.len() {
+        let argument = &arguments[index];
+        if argument == "--fact" {
+            index += 2;
+            continue;
+        }
+        if argument.starts_with('-') {
+            index += 1;
+            continue;
+        }
+        if plugins.iter().any(|(name, _)| name == argument) {
+            arguments.insert(index, ".".to_string());
+        }
+        break;
+    }
+    return arguments;
+}
@@ -0,0 +1,61 @@
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::error::{Error, Result};
+
+/// Run `command` with `sh -c` in `directory` with extra `environment` variables and return its
+/// standard output.
+pub fn output<P: AsRef<Path>>(command: &str, directory: P, environment: &[(String, String)]) -> Result<Vec<u8>> {
+    let output = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .current_dir(directory)
+        .envs(environment.iter().map(|(name, value)| (name, value)))
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|e| Error::new(&format!("Failed to run `{}`.", command)).with_inner_error(&e))?;
+    if !output.status.success() {
+        return Err(Error::new(&format!("Failed to run `{}`, {}.", command, output.status))
+            .with_inner_error(&String::from_utf8_lossy(&output.stderr).trim()));
+    }
+    return Ok(output.stdout);
+}
+
+/// Run `command` with `sh -c` in `directory` with extra `environment` variables, its output goes to
+/// ours. It is killed when it runs longer than `timeout`.
+pub fn run<P: AsRef<Path>>(
+    command: &str,
+    directory: P,
+    environment: &[(String, String)],
+    timeout: Option<Duration>,
+) -> Result<()> {
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .current_dir(directory)
+        .envs(environment.iter().map(|(name, value)| (name, value)))
+        .stdin(Stdio::null())
+        .spawn()
+        .map_err(|e| Error::new(&format!("Failed to run `{}`.", command)).with_inner_error(&e))?;
+    let start = Instant::now();
+    loop {
+        let status = child
+            .try_wait()
+            .map_err(|e| Error::new(&format!("Failed to run `{}`.", command)).with_inner_error(&e))?;
+        match status {
+            Some(status) if status.success() => return Ok(()),
+            Some(status) => return Err(Error::new(&format!("Failed to run `{}`, {}.", command, status))),
+            None => {}
+        }
+        if let Some(timeout) = timeout {
+            if start.elapsed() >= timeout {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(Error::new(&format!("`{}` timed out after {} seconds.", command, timeout.as_secs())));
+            }
+        }
+        thread::sleep(Duration::from_millis(20));
+    }
+}
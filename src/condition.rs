@@ -0,0 +1,547 @@
+use std::env;
+use std::fmt;
+use std::path::Path;
+use std::sync::Arc;
+
+use regex::Regex;
+
+use crate::error::{Error, Result};
+use crate::filesystem::Filesystem;
+use crate::path_extension::PathExtension;
+use crate::script::{self, Script};
+use crate::variables::Variables;
+
+/// A `when` expression such as `os == "linux" && hostname =~ "^build-"`.
+///
+/// Identifiers resolve to variables and facts, `env.NAME` to environment variables and
+/// `exists("path")` checks the filesystem. A bare value is true unless it is empty, `false` or `0`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Condition {
+    expression: Expression,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Expression {
+    Or(Box<Expression>, Box<Expression>),
+    And(Box<Expression>, Box<Expression>),
+    Not(Box<Expression>),
+    Compare(Operand, Operator, Operand),
+    Value(Operand),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Operand {
+    Literal(String),
+    Identifier(String),
+    Call(String, Vec<Operand>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Operator {
+    Equal,
+    NotEqual,
+    Match,
+    NotMatch,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Literal(String),
+    Identifier(String),
+    Operator(Operator),
+    And,
+    Or,
+    Not,
+    LeftParen,
+    RightParen,
+    Comma,
+}
+
+impl Condition {
+    pub fn parse(source: &str) -> Result<Condition> {
+        let tokens = tokenize(source)
+            .map_err(|e| Error::new(&format!("Invalid condition `{}`.", source)).with_inner_error(&e))?;
+        let mut parser = Parser { tokens, position: 0 };
+        let expression = parser
+            .parse_or()
+            .and_then(|expression| match parser.peek() {
+                None => Ok(expression),
+                Some(token) => Err(Error::new(&format!("Unexpected token `{:?}`.", token))),
+            })
+            .map_err(|e| Error::new(&format!("Invalid condition `{}`.", source)).with_inner_error(&e))?;
+        return Ok(Condition { expression });
+    }
+
+    /// Evaluate the condition and return the clause that made it false, if any.
+    pub fn failing_clause(&self, filesystem: &dyn Filesystem, variables: &Variables) -> Result<Option<String>> {
+        return self.expression.failing_clause(filesystem, variables);
+    }
+}
+
+/// The clause of a `when` that is false, for a condition or a script file in `workspace`.
+pub fn failing_clause(
+    filesystem: &Arc<dyn Filesystem>,
+    workspace: &Path,
+    when: &Option<String>,
+    variables: &Variables,
+) -> Result<Option<String>> {
+    return match when {
+        Some(when) if script::is_file(when) => {
+            Script::load(filesystem.as_ref(), workspace, when)?.failing_clause(filesystem, workspace, variables)
+        }
+        Some(when) => Condition::parse(when)?.failing_clause(filesystem.as_ref(), variables),
+        None => Ok(None),
+    };
+}
+
+impl Expression {
+    fn evaluate(&self, filesystem: &dyn Filesystem, variables: &Variables) -> Result<bool> {
+        return match self {
+            Expression::Or(left, right) => Ok(left.evaluate(filesystem, variables)? || right.evaluate(filesystem, variables)?),
+            Expression::And(left, right) => Ok(left.evaluate(filesystem, variables)? && right.evaluate(filesystem, variables)?),
+            Expression::Not(expression) => Ok(!expression.evaluate(filesystem, variables)?),
+            Expression::Compare(left, operator, right) => {
+                let left = left.evaluate(filesystem, variables)?;
+                let right = right.evaluate(filesystem, variables)?;
+                match operator {
+                    Operator::Equal => Ok(left == right),
+                    Operator::NotEqual => Ok(left != right),
+                    Operator::Match => Ok(compile(&right)?.is_match(&left)),
+                    Operator::NotMatch => Ok(!compile(&right)?.is_match(&left)),
+                }
+            }
+            Expression::Value(operand) => Ok(is_truthy(&operand.evaluate(filesystem, variables)?)),
+        };
+    }
+
+    fn failing_clause(&self, filesystem: &dyn Filesystem, variables: &Variables) -> Result<Option<String>> {
+        return match self {
+            Expression::And(left, right) => match left.failing_clause(filesystem, variables)? {
+                Some(clause) => Ok(Some(clause)),
+                None => right.failing_clause(filesystem, variables),
+            },
+            _ => {
+                if self.evaluate(filesystem, variables)? {
+                    Ok(None)
+                } else {
+                    Ok(Some(self.to_string()))
+                }
+            }
+        };
+    }
+}
+
+impl Operand {
+    fn evaluate(&self, filesystem: &dyn Filesystem, variables: &Variables) -> Result<String> {
+        return match self {
+            Operand::Literal(value) => Ok(value.clone()),
+            Operand::Identifier(name) => {
+                if let Some(name) = name.strip_prefix("env.") {
+                    Ok(env::var(name).unwrap_or_default())
+                } else if name == "true" || name == "false" {
+                    Ok(name.clone())
+                } else {
+                    variables
+                        .get(name)
+                        .ok_or_else(|| Error::new(&format!("Undefined variable `{}`.", name)))
+                }
+            }
+            Operand::Call(name, arguments) => {
+                let arguments = arguments
+                    .iter()
+                    .map(|argument| argument.evaluate(filesystem, variables))
+                    .collect::<Result<Vec<String>>>()?;
+                match (name.as_str(), arguments.as_slice()) {
+                    ("exists", [path]) => Ok(Path::new(path)
+                        .absolutize()
+                        .map(|path| filesystem.actually_exists(&path))
+                        .unwrap_or(false)
+                        .to_string()),
+                    _ => Err(Error::new(&format!(
+                        "Unknown function `{}` with {} argument(s).",
+                        name,
+                        arguments.len()
+                    ))),
+                }
+            }
+        };
+    }
+}
+
+impl fmt::Display for Expression {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return match self {
+            Expression::Or(left, right) => write!(f, "{} || {}", left, right),
+            Expression::And(left, right) => write!(f, "{} && {}", left.grouped(), right.grouped()),
+            Expression::Not(expression) => write!(f, "!{}", expression.grouped()),
+            Expression::Compare(left, operator, right) => write!(f, "{} {} {}", left, operator, right),
+            Expression::Value(operand) => write!(f, "{}", operand),
+        };
+    }
+}
+
+impl Expression {
+    fn grouped(&self) -> String {
+        return match self {
+            Expression::Or(_, _) | Expression::And(_, _) | Expression::Compare(_, _, _) => format!("({})", self),
+            _ => self.to_string(),
+        };
+    }
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return match self {
+            Operand::Literal(value) => write!(f, "{:?}", value),
+            Operand::Identifier(name) => write!(f, "{}", name),
+            Operand::Call(name, arguments) => {
+                let arguments: Vec<String> = arguments.iter().map(|argument| argument.to_string()).collect();
+                write!(f, "{}({})", name, arguments.join(", "))
+            }
+        };
+    }
+}
+
+impl fmt::Display for Operator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return write!(
+            f,
+            "{}",
+            match self {
+                Operator::Equal => "==",
+                Operator::NotEqual => "!=",
+                Operator::Match => "=~",
+                Operator::NotMatch => "!~",
+            }
+        );
+    }
+}
+
+fn compile(pattern: &str) -> Result<Regex> {
+    return Regex::new(pattern)
+        .map_err(|e| Error::new(&format!("Invalid regular expression `{}`.", pattern)).with_inner_error(&e));
+}
+
+fn is_truthy(value: &str) -> bool {
+    return !(value.is_empty() || value == "false" || value == "0");
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut tokens = vec![];
+    let mut index = 0;
+    while index < chars.len() {
+        let c = chars[index];
+        let next = chars.get(index + 1).cloned();
+        match c {
+            ' ' | '\t' | '\n' | '\r' => index += 1,
+            '(' => {
+                tokens.push(Token::LeftParen);
+                index += 1;
+            }
+            ')' => {
+                tokens.push(Token::RightParen);
+                index += 1;
+            }
+            ',' => {
+                tokens.push(Token::Comma);
+                index += 1;
+            }
+            '&' if next == Some('&') => {
+                tokens.push(Token::And);
+                index += 2;
+            }
+            '|' if next == Some('|') => {
+                tokens.push(Token::Or);
+                index += 2;
+            }
+            '=' if next == Some('=') => {
+                tokens.push(Token::Operator(Operator::Equal));
+                index += 2;
+            }
+            '=' if next == Some('~') => {
+                tokens.push(Token::Operator(Operator::Match));
+                index += 2;
+            }
+            '!' if next == Some('=') => {
+                tokens.push(Token::Operator(Operator::NotEqual));
+                index += 2;
+            }
+            '!' if next == Some('~') => {
+                tokens.push(Token::Operator(Operator::NotMatch));
+                index += 2;
+            }
+            '!' => {
+                tokens.push(Token::Not);
+                index += 1;
+            }
+            '"' | '\'' => {
+                let mut value = String::new();
+                index += 1;
+                loop {
+                    match chars.get(index) {
+                        None => return Err(Error::new("Unterminated string.")),
+                        Some(&quote) if quote == c => break,
+                        Some('\\') if chars.get(index + 1).is_some() => {
+                            value.push(chars[index + 1]);
+                            index += 2;
+                        }
+                        Some(&other) => {
+                            value.push(other);
+                            index += 1;
+                        }
+                    }
+                }
+                tokens.push(Token::Literal(value));
+                index += 1;
+            }
+            c if c.is_alphanumeric() || c == '_' || c == '.' || c == '-' => {
+                let start = index;
+                while index < chars.len()
+                    && (chars[index].is_alphanumeric() || chars[index] == '_' || chars[index] == '.' || chars[index] == '-')
+                {
+                    index += 1;
+                }
+                let word: String = chars[start..index].iter().collect();
+                if word.chars().all(|c| c.is_ascii_digit() || c == '.' || c == '-') {
+                    tokens.push(Token::Literal(word));
+                } else {
+                    tokens.push(Token::Identifier(word));
+                }
+            }
+            _ => return Err(Error::new(&format!("Unexpected character `{}`.", c))),
+        }
+    }
+    return Ok(tokens);
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        return self.tokens.get(self.position);
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        return token;
+    }
+
+    fn parse_or(&mut self) -> Result<Expression> {
+        let mut expression = self.parse_and()?;
+        while self.peek() == Some(&Token::Or) {
+            self.next();
+            expression = Expression::Or(Box::new(expression), Box::new(self.parse_and()?));
+        }
+        return Ok(expression);
+    }
+
+    fn parse_and(&mut self) -> Result<Expression> {
+        let mut expression = self.parse_unary()?;
+        while self.peek() == Some(&Token::And) {
+            self.next();
+            expression = Expression::And(Box::new(expression), Box::new(self.parse_unary()?));
+        }
+        return Ok(expression);
+    }
+
+    fn parse_unary(&mut self) -> Result<Expression> {
+        return match self.peek() {
+            Some(Token::Not) => {
+                self.next();
+                Ok(Expression::Not(Box::new(self.parse_unary()?)))
+            }
+            Some(Token::LeftParen) => {
+                self.next();
+                let expression = self.parse_or()?;
+                match self.next() {
+                    Some(Token::RightParen) => Ok(expression),
+                    _ => Err(Error::new("Expected `)`.")),
+                }
+            }
+            _ => {
+                let left = self.parse_operand()?;
+                if let Some(Token::Operator(operator)) = self.peek().cloned() {
+                    self.next();
+                    let right = self.parse_operand()?;
+                    Ok(Expression::Compare(left, operator, right))
+                } else {
+                    Ok(Expression::Value(left))
+                }
+            }
+        };
+    }
+
+    fn parse_operand(&mut self) -> Result<Operand> {
+        return match self.next() {
+            Some(Token::Literal(value)) => Ok(Operand::Literal(value)),
+            Some(Token::Identifier(name)) => {
+                if self.peek() != Some(&Token::LeftParen) {
+                    return Ok(Operand::Identifier(name));
+                }
+                self.next();
+                let mut arguments = vec![];
+                if self.peek() == Some(&Token::RightParen) {
+                    self.next();
+                    return Ok(Operand::Call(name, arguments));
+                }
+                loop {
+                    arguments.push(self.parse_operand()?);
+                    match self.next() {
+                        Some(Token::Comma) => continue,
+                        Some(Token::RightParen) => break,
+                        _ => return Err(Error::new("Expected `,` or `)`.")),
+                    }
+                }
+                Ok(Operand::Call(name, arguments))
+            }
+            Some(token) => Err(Error::new(&format!("Unexpected token `{:?}`.", token))),
+            None => Err(Error::new("Unexpected end of condition.")),
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::facts::Facts;
+    use crate::filesystem::MemoryFilesystem;
+
+    fn variables() -> Variables {
+        let mut facts = Facts::new();
+        facts.insert("os", "linux");
+        facts.insert("arch", "x86_64");
+        facts.insert("hostname", "build-01");
+        facts.insert("empty", "");
+        return Variables::new(facts);
+    }
+
+    fn failing(source: &str) -> Option<String> {
+        let filesystem = MemoryFilesystem::new();
+        filesystem.add_file("/home/me/.vimrc", b"").unwrap();
+        return Condition::parse(source).unwrap().failing_clause(&filesystem, &variables()).unwrap();
+    }
+
+    fn value(name: &str) -> Box<Expression> {
+        return Box::new(Expression::Value(Operand::Identifier(name.to_string())));
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        assert_eq!(
+            Condition::parse("a || b && c").unwrap().expression,
+            Expression::Or(value("a"), Box::new(Expression::And(value("b"), value("c"))))
+        );
+        assert_eq!(
+            Condition::parse("(a || b) && c").unwrap().expression,
+            Expression::And(Box::new(Expression::Or(value("a"), value("b"))), value("c"))
+        );
+    }
+
+    #[test]
+    fn not_binds_tightest() {
+        assert_eq!(
+            Condition::parse("!a && b").unwrap().expression,
+            Expression::And(Box::new(Expression::Not(value("a"))), value("b"))
+        );
+        assert_eq!(
+            Condition::parse("!(a || b)").unwrap().expression,
+            Expression::Not(Box::new(Expression::Or(value("a"), value("b"))))
+        );
+        assert_eq!(Condition::parse("!!a").unwrap().expression, Expression::Not(Box::new(Expression::Not(value("a")))));
+    }
+
+    #[test]
+    fn parse_operands() {
+        assert_eq!(
+            Condition::parse(r#"os == 'linux' && version != "1.2" && exists("~/.vimrc", env.HOME)"#).unwrap().expression,
+            Expression::And(
+                Box::new(Expression::And(
+                    Box::new(Expression::Compare(
+                        Operand::Identifier("os".to_string()),
+                        Operator::Equal,
+                        Operand::Literal("linux".to_string())
+                    )),
+                    Box::new(Expression::Compare(
+                        Operand::Identifier("version".to_string()),
+                        Operator::NotEqual,
+                        Operand::Literal("1.2".to_string())
+                    )),
+                )),
+                Box::new(Expression::Value(Operand::Call(
+                    "exists".to_string(),
+                    vec![Operand::Literal("~/.vimrc".to_string()), Operand::Identifier("env.HOME".to_string())]
+                ))),
+            )
+        );
+        assert_eq!(
+            Condition::parse(r#"name =~ "a\"b""#).unwrap().expression,
+            Expression::Compare(Operand::Identifier("name".to_string()), Operator::Match, Operand::Literal("a\"b".to_string()))
+        );
+    }
+
+    #[test]
+    fn parse_rejects_invalid_conditions() {
+        for source in ["", "os ==", "(os", "os)", "os linux", "os & arch", r#"os == "linux"#, "os == $", "exists(a b)"] {
+            assert!(Condition::parse(source).is_err(), "`{}` should not parse", source);
+        }
+    }
+
+    #[test]
+    fn evaluate_comparisons_and_values() {
+        assert_eq!(failing(r#"os == "linux""#), None);
+        assert_eq!(failing(r#"os != "darwin""#), None);
+        assert_eq!(failing(r#"hostname =~ "^build-\\d+$""#), None);
+        assert_eq!(failing(r#"hostname !~ "^desk""#), None);
+        assert_eq!(failing("os"), None);
+        assert_eq!(failing("true"), None);
+        assert_eq!(failing("!false"), None);
+        assert_eq!(failing("empty"), Some("empty".to_string()));
+        assert_eq!(failing("0"), Some("\"0\"".to_string()));
+        assert_eq!(failing(r#"exists("/home/me/.vimrc")"#), None);
+        assert_eq!(failing(r#"!exists("/home/me/.gvimrc")"#), None);
+    }
+
+    #[test]
+    fn evaluate_with_precedence() {
+        assert_eq!(failing("true || false && false"), None);
+        assert_eq!(failing("false && false || true"), None);
+        assert_eq!(failing("!true || true"), None);
+        assert_eq!(failing("!(true || true)"), Some("!(true || true)".to_string()));
+    }
+
+    #[test]
+    fn failing_clause_of_and_is_the_first_false_operand() {
+        assert_eq!(failing(r#"os == "linux" && arch == "arm64""#), Some(r#"arch == "arm64""#.to_string()));
+        assert_eq!(
+            failing(r#"(os == "linux" && hostname == "desk") && arch == "arm64""#),
+            Some(r#"hostname == "desk""#.to_string())
+        );
+        assert_eq!(failing(r#"os == "darwin" && arch == "arm64""#), Some(r#"os == "darwin""#.to_string()));
+    }
+
+    #[test]
+    fn failing_clause_of_or_and_not_is_the_whole_expression() {
+        assert_eq!(
+            failing(r#"os == "darwin" || arch == "arm64""#),
+            Some(r#"os == "darwin" || arch == "arm64""#.to_string())
+        );
+        assert_eq!(failing(r#"!(os == "linux")"#), Some(r#"!(os == "linux")"#.to_string()));
+        assert_eq!(
+            failing(r#"os == "linux" && !(arch == "x86_64" || false)"#),
+            Some(r#"!(arch == "x86_64" || false)"#.to_string())
+        );
+    }
+
+    #[test]
+    fn evaluate_errors() {
+        let variables = variables();
+        for source in ["missing", r#"os =~ "(""#, "unknown()", r#"exists("a", "b")"#] {
+            assert!(Condition::parse(source).unwrap().failing_clause(&MemoryFilesystem::new(), &variables).is_err(), "`{}` should fail", source);
+        }
+    }
+}
@@ -1,31 +1,171 @@
-use std::collections::HashMap;
+use std::fmt;
+use std::marker::PhantomData;
 use std::path::Path;
 use std::path::PathBuf;
 
-use serde::{Deserialize, Serialize};
+use serde::de::{self, Error as _, MapAccess, SeqAccess, Visitor};
+use regex::Regex;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
 use crate::error::{Error, Result};
+use crate::filesystem::{Filesystem, RealFilesystem};
 use crate::path_extension::PathExtension;
 
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
 pub struct GroupConfiguration {
     #[serde(default)]
-    pub links: Option<HashMap<String, LinkConfiguration>>,
+    pub links: Option<Links>,
     #[serde(default)]
     pub clean: Option<CleanConfiguration>,
     #[serde(default = "default_relative")]
     pub relative: Option<bool>,
     #[serde(default = "default_force")]
     pub force: Option<bool>,
+    #[serde(default)]
+    pub variables: Option<OrderedMap<String>>,
+    /// A Rhai script, or a `.rhai` file, returning a map of more variables.
+    #[serde(default)]
+    pub script: Option<String>,
+    #[serde(default)]
+    pub when: Option<String>,
+    #[serde(default)]
+    pub groups: Option<OrderedMap<GroupConfiguration>>,
+    #[serde(default)]
+    pub layout: Option<String>,
+    #[serde(default)]
+    pub after: Option<Vec<String>>,
+    #[serde(default)]
+    pub before: Option<Vec<String>>,
+    #[serde(default)]
+    pub hooks: Option<HooksConfiguration>,
+    #[serde(default)]
+    pub tasks: Option<OrderedMap<TaskConfiguration>>,
 }
 
-#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
 pub struct LinkConfiguration {
+    /// The link path, only in the list form of `links`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub link: Option<String>,
+    /// Every location of a target-keyed entry, the key of the entry is the target then.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub links: Option<Vec<String>>,
+    #[serde(default)]
     pub target: String,
     #[serde(default = "default_target_relative")]
     pub relative: Option<bool>,
     #[serde(default = "default_force")]
     pub force: Option<bool>,
+    #[serde(default)]
+    pub when: Option<String>,
+    #[serde(default)]
+    pub mode: Option<String>,
+    #[serde(default)]
+    pub block: Option<String>,
+    #[serde(default)]
+    pub command: Option<String>,
+    #[serde(default)]
+    pub inputs: Option<Vec<String>>,
+    #[serde(default)]
+    pub contents: Option<bool>,
+    #[serde(default)]
+    pub depth: Option<usize>,
+    #[serde(default)]
+    pub pattern: Option<String>,
+    #[serde(default)]
+    pub exclude: Option<Vec<String>>,
+    #[serde(default)]
+    pub after: Option<Vec<String>>,
+    #[serde(default)]
+    pub before: Option<Vec<String>>,
+    #[serde(default)]
+    pub hooks: Option<HooksConfiguration>,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct TaskConfiguration {
+    pub command: String,
+    #[serde(default)]
+    pub creates: Option<String>,
+    #[serde(default)]
+    pub unless: Option<String>,
+    #[serde(default)]
+    pub onlyif: Option<String>,
+    #[serde(default)]
+    pub after: Option<Vec<String>>,
+    #[serde(default)]
+    pub when: Option<String>,
+    #[serde(default)]
+    pub cwd: Option<String>,
+    /// Seconds the command may run before it is killed.
+    #[serde(default)]
+    pub timeout: Option<u64>,
+}
+
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct HooksConfiguration {
+    #[serde(default, deserialize_with = "deserialize_hooks")]
+    pub pre_create: Option<Vec<HookConfiguration>>,
+    #[serde(default, deserialize_with = "deserialize_hooks")]
+    pub post_create: Option<Vec<HookConfiguration>>,
+    #[serde(default, deserialize_with = "deserialize_hooks")]
+    pub pre_delete: Option<Vec<HookConfiguration>>,
+    #[serde(default, deserialize_with = "deserialize_hooks")]
+    pub post_delete: Option<Vec<HookConfiguration>>,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct HookConfiguration {
+    #[serde(default)]
+    pub command: Option<String>,
+    /// A Rhai script, or a `.rhai` file, run instead of a command.
+    #[serde(default)]
+    pub script: Option<String>,
+    #[serde(default)]
+    pub cwd: Option<String>,
+    /// Seconds the command may run before it is killed.
+    #[serde(default)]
+    pub timeout: Option<u64>,
+    #[serde(default)]
+    pub only_changed: Option<bool>,
+}
+
+/// Hooks of an event are a command, a hook configuration or a list of them.
+fn deserialize_hooks<'de, D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Option<Vec<HookConfiguration>>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Hook {
+        Command(String),
+        Configuration(HookConfiguration),
+    }
+
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Hooks {
+        // Before `One`, a hook configuration could be read from a list too.
+        Many(Vec<Hook>),
+        One(Hook),
+    }
+
+    let hooks = match Hooks::deserialize(deserializer)? {
+        Hooks::One(hook) => vec![hook],
+        Hooks::Many(hooks) => hooks,
+    };
+    return Ok(Some(
+        hooks
+            .into_iter()
+            .map(|hook| match hook {
+                Hook::Command(command) => HookConfiguration {
+                    command: Some(command),
+                    script: None,
+                    cwd: None,
+                    timeout: None,
+                    only_changed: None,
+                },
+                Hook::Configuration(hook) => hook,
+            })
+            .collect(),
+    ));
 }
 
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
@@ -37,11 +177,176 @@ pub struct TargetConfiguration {
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
 pub struct CleanConfiguration {
     #[serde(default)]
-    pub targets: Option<HashMap<String, TargetConfiguration>>,
+    pub targets: Option<OrderedMap<TargetConfiguration>>,
     #[serde(default)]
     pub force: Option<bool>,
 }
 
+/// A map that keeps its entries in the order they are written in the configuration.
+#[derive(Debug, PartialEq)]
+pub struct OrderedMap<V>(pub Vec<(String, V)>);
+
+impl<V> OrderedMap<V> {
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &V)> {
+        return self.0.iter().map(|(key, value)| (key, value));
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &V> {
+        return self.0.iter().map(|(_, value)| value);
+    }
+}
+
+impl<'a, V> IntoIterator for &'a OrderedMap<V> {
+    type Item = (&'a String, &'a V);
+    type IntoIter = Box<dyn Iterator<Item = (&'a String, &'a V)> + 'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        return Box::new(self.iter());
+    }
+}
+
+impl<V: Serialize> Serialize for OrderedMap<V> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        return serializer.collect_map(self.iter());
+    }
+}
+
+impl<'de, V: Deserialize<'de>> Deserialize<'de> for OrderedMap<V> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        struct OrderedMapVisitor<V>(PhantomData<V>);
+
+        impl<'de, V: Deserialize<'de>> Visitor<'de> for OrderedMapVisitor<V> {
+            type Value = OrderedMap<V>;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                return formatter.write_str("a map");
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> std::result::Result<Self::Value, A::Error> {
+                let mut entries = vec![];
+                while let Some(entry) = map.next_entry()? {
+                    entries.push(entry);
+                }
+                return Ok(OrderedMap(entries));
+            }
+        }
+
+        return deserializer.deserialize_map(OrderedMapVisitor(PhantomData));
+    }
+}
+
+/// The `links` of a group in the order they are written. They are either a map from link path to
+/// its configuration or just its target, or a list of configurations with a `link` or just link
+/// paths. Target-keyed entries with `links` are keyed by their target instead.
+#[derive(Debug, PartialEq)]
+pub struct Links(pub Vec<(String, LinkConfiguration)>);
+
+impl Links {
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &LinkConfiguration)> {
+        return self.0.iter().map(|(link, configuration)| (link, configuration));
+    }
+}
+
+impl<'a> IntoIterator for &'a Links {
+    type Item = (&'a String, &'a LinkConfiguration);
+    type IntoIter = Box<dyn Iterator<Item = (&'a String, &'a LinkConfiguration)> + 'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        return Box::new(self.iter());
+    }
+}
+
+impl Serialize for Links {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        return serializer.collect_map(self.iter());
+    }
+}
+
+impl<'de> Deserialize<'de> for Links {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        struct LinksVisitor;
+
+        impl<'de> Visitor<'de> for LinksVisitor {
+            type Value = Links;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                return formatter.write_str("a map or a list of links");
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> std::result::Result<Self::Value, A::Error> {
+                let mut links = vec![];
+                while let Some((link, entry)) = map.next_entry::<String, MapEntry>()? {
+                    links.push((link, entry.0));
+                }
+                return Ok(Links(links));
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> std::result::Result<Self::Value, A::Error> {
+                let mut links = vec![];
+                while let Some(entry) = seq.next_element::<ListEntry>()? {
+                    let link = match (&entry.0.link, &entry.0.links) {
+                        (Some(link), _) => link.clone(),
+                        (None, Some(_)) if !entry.0.target.is_empty() => entry.0.target.clone(),
+                        _ => return Err(A::Error::missing_field("link")),
+                    };
+                    links.push((link, entry.0));
+                }
+                return Ok(Links(links));
+            }
+        }
+
+        return deserializer.deserialize_any(LinksVisitor);
+    }
+}
+
+/// A link configuration, or a string that is its target in the map form and its link path in the
+/// list form.
+struct EntryVisitor {
+    list: bool,
+}
+
+impl<'de> Visitor<'de> for EntryVisitor {
+    type Value = LinkConfiguration;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        return formatter.write_str("a link configuration or a path");
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> std::result::Result<Self::Value, E> {
+        return Ok(if self.list {
+            LinkConfiguration {
+                link: Some(value.to_string()),
+                ..Default::default()
+            }
+        } else {
+            LinkConfiguration {
+                target: value.to_string(),
+                ..Default::default()
+            }
+        });
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, map: A) -> std::result::Result<Self::Value, A::Error> {
+        return LinkConfiguration::deserialize(de::value::MapAccessDeserializer::new(map));
+    }
+}
+
+struct MapEntry(LinkConfiguration);
+
+impl<'de> Deserialize<'de> for MapEntry {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        return deserializer.deserialize_any(EntryVisitor { list: false }).map(MapEntry);
+    }
+}
+
+struct ListEntry(LinkConfiguration);
+
+impl<'de> Deserialize<'de> for ListEntry {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        return deserializer.deserialize_any(EntryVisitor { list: true }).map(ListEntry);
+    }
+}
+
 fn default_relative() -> Option<bool> {
     return Some(true);
 }
@@ -54,29 +359,78 @@ fn default_force() -> Option<bool> {
     return None;
 }
 
-pub fn detect_configuration_path<P: AsRef<Path>>(directory: P) -> Option<PathBuf> {
+pub fn detect_configuration_path<P: AsRef<Path>>(filesystem: &dyn Filesystem, directory: P) -> Option<PathBuf> {
     let path = directory.as_ref().absolutize().ok()?;
-    if path.exists() && path.is_dir() {
+    if filesystem.is_dir(&path) {
         let json_options_path = path.join("idot.json");
-        if json_options_path.exists() && json_options_path.is_file() {
+        if filesystem.is_file(&json_options_path) {
             return Some(json_options_path);
         }
         let toml_options_path = path.join("idot.toml");
-        if toml_options_path.exists() && toml_options_path.is_file() {
+        if filesystem.is_file(&toml_options_path) {
             return Some(toml_options_path);
         }
         let yaml_options_path = path.join("idot.yaml");
-        if yaml_options_path.exists() && yaml_options_path.is_file() {
+        if filesystem.is_file(&yaml_options_path) {
             return Some(yaml_options_path);
         }
     }
     return None;
 }
 
+/// Replace `from` with `to` wherever it is a whole string in the configuration file, keeping the
+/// rest of the file as it is written. Returns how many were replaced.
+///
+/// Quoted strings match in every format. In YAML plain scalars match too, as a key or after `: `,
+/// `- `, `[`, `{` or `,`, but not as part of a longer scalar like `~/.my-vimrc`.
+pub fn rename_target(filesystem: &dyn Filesystem, path: &Path, from: &str, to: &str) -> Result<usize> {
+    let content = filesystem
+        .read_to_string(path)
+        .map_err(|e| Error::new("Failed to load configuration file.").with_inner_error(&e))?;
+    let from = regex::escape(from);
+    let mut patterns = vec![
+        (format!(r#""(?P<value>{})""#, from), None),
+        (format!("'(?P<value>{})'", from), None),
+    ];
+    if path.extension().and_then(|extension| extension.to_str()) == Some("yaml") {
+        // The end of a plain scalar isn't consumed, it may start the next one, e.g. `[a, a]`.
+        patterns.push((
+            format!(r"(?m)(?:^[ \t]*|:[ \t]+|-[ \t]+|[\[{{,][ \t]*)(?P<value>{})", from),
+            Some(Regex::new(r"^(?:[ \t]*(?:\r?\n|$|[,\]}])|[ \t]+#|:(?:[ \t]|\r?\n|$))").unwrap()),
+        ));
+    }
+    let mut content = content;
+    let mut count = 0;
+    for (pattern, end) in patterns {
+        let regex = Regex::new(&pattern).unwrap();
+        let mut renamed = String::new();
+        let mut last = 0;
+        for captures in regex.captures_iter(&content) {
+            let value = captures.name("value").unwrap();
+            if end.as_ref().is_some_and(|end| !end.is_match(&content[value.end()..])) {
+                continue;
+            }
+            renamed.push_str(&content[last..value.start()]);
+            renamed.push_str(to);
+            last = value.end();
+            count += 1;
+        }
+        renamed.push_str(&content[last..]);
+        content = renamed;
+    }
+    if count > 0 {
+        filesystem
+            .write(path, content.as_bytes())
+            .map_err(|e| Error::new("Failed to write configuration file.").with_inner_error(&e))?;
+    }
+    return Ok(count);
+}
+
 pub trait GroupConfigurationLoader {
     fn load<P: AsRef<Path>>(&self, path: P) -> Result<GroupConfiguration>;
 }
 
+#[derive(Default)]
 pub struct DefaultGroupConfigurationLoader {}
 
 impl DefaultGroupConfigurationLoader {
@@ -87,7 +441,7 @@ impl DefaultGroupConfigurationLoader {
 
 impl GroupConfigurationLoader for DefaultGroupConfigurationLoader {
     fn load<P: AsRef<Path>>(&self, workspace: P) -> Result<GroupConfiguration> {
-        return match detect_configuration_path(workspace) {
+        return match detect_configuration_path(&RealFilesystem, workspace) {
             Some(path) => match path.extension() {
                 Some(extemsion) => match extemsion.to_str() {
                     Some("json") => {
@@ -149,3 +503,44 @@ impl GroupConfigurationLoader for YamlGroupConfigurationLoader {
             .map_err(|e| Error::new("Failed to convert configuration.").with_inner_error(&e));
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filesystem::MemoryFilesystem;
+
+    fn rename(name: &str, content: &str) -> (String, usize) {
+        let filesystem = MemoryFilesystem::new();
+        let path = Path::new("/dotfiles").join(name);
+        filesystem.add_file(&path, content.as_bytes()).unwrap();
+        let count = rename_target(&filesystem, &path, "vimrc", "vim/vimrc").unwrap();
+        return (filesystem.read_to_string(&path).unwrap(), count);
+    }
+
+    #[test]
+    fn rename_target_in_json() {
+        let (content, count) = rename("idot.json", r#"{"links": {"~/.vimrc": "vimrc", "~/.my-vimrc": "my-vimrc", "~/.gvimrc": ["vimrc", "vimrc.bak"]}}"#);
+        assert_eq!(content, r#"{"links": {"~/.vimrc": "vim/vimrc", "~/.my-vimrc": "my-vimrc", "~/.gvimrc": ["vim/vimrc", "vimrc.bak"]}}"#);
+        assert_eq!(count, 2);
+    }
+
+    #[test]
+    fn rename_target_in_toml() {
+        let (content, count) = rename("idot.toml", "[links]\n\"~/.vimrc\" = \"vimrc\"\n\"~/.exrc\" = 'vimrc'\n\"~/.my-vimrc\" = \"my-vimrc\"\n");
+        assert_eq!(content, "[links]\n\"~/.vimrc\" = \"vim/vimrc\"\n\"~/.exrc\" = 'vim/vimrc'\n\"~/.my-vimrc\" = \"my-vimrc\"\n");
+        assert_eq!(count, 2);
+    }
+
+    #[test]
+    fn rename_target_in_yaml() {
+        let (content, count) = rename(
+            "idot.yaml",
+            "links:\n  ~/.vimrc: vimrc\n  ~/.my-vimrc: my-vimrc\n  ~/.exrc: vimrc # shared\n  ~/.gvimrc: [vimrc, vimrc]\n  ~/.nvimrc:\n    target: \"vimrc\"\n    inputs:\n      - vimrc\n      - vimrc.bak\nvimrc:\n  - ~/.vimrc-vimrc\n",
+        );
+        assert_eq!(
+            content,
+            "links:\n  ~/.vimrc: vim/vimrc\n  ~/.my-vimrc: my-vimrc\n  ~/.exrc: vim/vimrc # shared\n  ~/.gvimrc: [vim/vimrc, vim/vimrc]\n  ~/.nvimrc:\n    target: \"vim/vimrc\"\n    inputs:\n      - vim/vimrc\n      - vimrc.bak\nvim/vimrc:\n  - ~/.vimrc-vimrc\n",
+        );
+        assert_eq!(count, 7);
+    }
+}
@@ -0,0 +1,175 @@
+use std::io;
+use std::path::{Path, PathBuf};
+
+use log::debug;
+
+use crate::error::{Error, Result};
+use crate::filesystem::Filesystem;
+use crate::linker::{Link, LinkStatus, Linker, SYMLINK_MODE};
+use crate::report::{Entry, Report};
+use crate::state::{ManifestEntry, State, StateEntry};
+
+/// Children of `directory` to link, relative to it. Directories deeper than `depth` are linked as
+/// a whole, without a depth every file is linked on its own.
+fn children(filesystem: &dyn Filesystem, directory: &Path, depth: Option<usize>) -> io::Result<Vec<PathBuf>> {
+    let mut result = vec![];
+    for entry in filesystem.read_dir(directory)? {
+        let name = PathBuf::from(entry.file_name().unwrap());
+        let unfold = filesystem.is_dir(&entry) && !filesystem.is_symbolic(&entry) && depth.is_none_or(|depth| depth > 1);
+        if unfold {
+            for child in children(filesystem, &entry, depth.map(|depth| depth - 1))? {
+                result.push(name.join(child));
+            }
+        } else {
+            result.push(name);
+        }
+    }
+    return Ok(result);
+}
+
+/// Every child as a pair of link path and target.
+fn expand(link: &Link) -> Result<Vec<(PathBuf, PathBuf)>> {
+    if !link.filesystem.is_dir(&link.target) {
+        return Err(Error::new(&format!(
+            "`{}` is not a directory, only the contents of a directory can be linked.",
+            link.target.to_str().unwrap()
+        )));
+    }
+    let children = children(&*link.filesystem, &link.target, link.depth)
+        .map_err(|e| Error::new("Failed to read target directory.").with_inner_error(&e))?;
+    return Ok(children
+        .into_iter()
+        .map(|child| (link.path.join(&child), link.target.join(&child)))
+        .collect());
+}
+
+pub fn status(link: &Link, _state: &State) -> Result<LinkStatus> {
+    let children = expand(link)?;
+    let mut linked = 0;
+    let mut conflicting = 0;
+    for (path, target) in &children {
+        match Linker::symbolic_link_status(&*link.filesystem, path, target, link.relative) {
+            LinkStatus::Active => linked += 1,
+            _ if link.filesystem.actually_exists(path) => conflicting += 1,
+            _ => {}
+        }
+    }
+    if conflicting > 0 {
+        return Ok(LinkStatus::Modified(format!("{} conflicting", conflicting)));
+    }
+    if linked == children.len() {
+        return Ok(LinkStatus::Active);
+    }
+    if linked == 0 {
+        return Ok(LinkStatus::Inactive);
+    }
+    return Ok(LinkStatus::Outdated(format!("{} of {} linked", linked, children.len())));
+}
+
+pub fn create(link: &Link, state: &mut State, simulate: bool) -> Result<()> {
+    let filesystem = &*link.filesystem;
+    if filesystem.is_symbolic(&link.path) {
+        if !link.force {
+            return Err(Error::new(&format!(
+                "`{}` is a symbolic link, use `--force` to replace it with a directory.",
+                link.path.to_str().unwrap()
+            )));
+        }
+        debug!("Force to delete symbolic link: {}.", link.path.to_str().unwrap());
+        if !simulate {
+            filesystem.remove(&link.path).map_err(|e| Error::new("Failed to delete symbolic link.").with_inner_error(&e))?;
+        }
+    }
+    if let LinkStatus::Modified(detail) = status(link, state)? {
+        if !link.force {
+            return Err(Error::new(&format!(
+                "`{}` has {} file(s), use `--force` to overwrite them.",
+                link.path.to_str().unwrap(),
+                detail
+            )));
+        }
+    }
+    let mut created = recorded(link, state);
+    for (path, target) in expand(link)? {
+        if let LinkStatus::Active = Linker::symbolic_link_status(&*link.filesystem, &path, &target, link.relative) {
+            continue;
+        }
+        for directory in missing_directories(filesystem, &link.path, &path) {
+            debug!("Create directory: {}.", directory.to_str().unwrap());
+            if !simulate {
+                filesystem.mkdir(&directory).map_err(|e| Error::new("Failed to create directory.").with_inner_error(&e))?;
+            }
+            let name = directory.strip_prefix(&link.path).unwrap().to_str().unwrap().to_string();
+            if !created.iter().any(|entry| entry.path == name) {
+                created.push(ManifestEntry { path: name, hash: None });
+            }
+        }
+        if simulate {
+            debug!("Create symbolic link : {} target {}.", path.to_str().unwrap(), target.to_str().unwrap());
+            continue;
+        }
+        Linker::create_symbolic_link(filesystem, &path, &target, link.relative, link.force, state, simulate)
+            .map_err(|e| Error::new(&format!("Failed to link `{}`.", path.to_str().unwrap())).with_inner_error(&e))?;
+    }
+    if !simulate {
+        state.set(
+            &link.path,
+            StateEntry {
+                mode: SYMLINK_MODE.to_string(),
+                target: link.target.to_str().unwrap().to_string(),
+                manifest: Some(created),
+                ..Default::default()
+            },
+        );
+    }
+    return Ok(());
+}
+
+pub fn delete(link: &Link, state: &mut State, simulate: bool, report: &mut Report) -> Result<()> {
+    for (path, target) in expand(link)? {
+        if let LinkStatus::Active = Linker::symbolic_link_status(&*link.filesystem, &path, &target, link.relative) {
+            debug!("Delete symbolic link: {}.", path.to_str().unwrap());
+            if !simulate {
+                link.filesystem.remove(&path).map_err(|e| Error::new("Failed to delete symbolic link.").with_inner_error(&e))?;
+            }
+        }
+    }
+    if simulate {
+        return Ok(());
+    }
+    for entry in recorded(link, state).iter().rev() {
+        let directory = link.path.join(&entry.path);
+        match link.filesystem.read_dir(&directory).map(|entries| entries.is_empty()) {
+            Ok(true) => {
+                debug!("Delete directory: {}.", directory.to_str().unwrap());
+                if let Err(error) = link.filesystem.remove(&directory) {
+                    report.push(Entry::Warning(format!("Failed to delete `{}`. {}", directory.to_str().unwrap(), error)));
+                }
+            }
+            _ => debug!("Keep directory: {}.", directory.to_str().unwrap()),
+        }
+    }
+    state.remove(&link.path);
+    return Ok(());
+}
+
+/// Directories that idot created for the contents of the link before.
+fn recorded(link: &Link, state: &State) -> Vec<ManifestEntry> {
+    return match state.get(&link.path) {
+        Some(entry) if entry.mode == SYMLINK_MODE => entry.manifest.clone().unwrap_or_default(),
+        _ => vec![],
+    };
+}
+
+/// Directories between `root` and the parent of `path` that don't exist yet, outermost first.
+fn missing_directories(filesystem: &dyn Filesystem, root: &Path, path: &Path) -> Vec<PathBuf> {
+    let mut result: Vec<PathBuf> = path
+        .ancestors()
+        .skip(1)
+        .take_while(|directory| directory.starts_with(root))
+        .filter(|directory| !filesystem.actually_exists(directory))
+        .map(|directory| directory.to_path_buf())
+        .collect();
+    result.reverse();
+    return result;
+}
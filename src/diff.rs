@@ -0,0 +1,366 @@
+use std::fmt;
+
+use crate::error::{Error, Result};
+
+/// Context lines that may be ignored at either end of a hunk when it doesn't apply as is.
+const MAX_FUZZ: usize = 2;
+
+/// A unified diff of one file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Patch {
+    hunks: Vec<Hunk>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Hunk {
+    old_start: usize,
+    new_start: usize,
+    lines: Vec<Line>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Line {
+    Context(String),
+    Remove(String),
+    Add(String),
+}
+
+/// Where a hunk was applied, relative to where the patch expected it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HunkResult {
+    pub line: usize,
+    pub offset: isize,
+    pub fuzz: usize,
+}
+
+impl Patch {
+    /// Parse the hunks of a unified diff. Each hunk is as long as the line counts of its header
+    /// say, anything between hunks, like `---` and `+++` file headers, is skipped.
+    pub fn parse(content: &str) -> Result<Patch> {
+        let mut hunks: Vec<Hunk> = vec![];
+        // Old and new lines the current hunk still has.
+        let (mut old, mut new) = (0, 0);
+        for line in content.lines() {
+            if old == 0 && new == 0 {
+                if line.starts_with("@@") {
+                    let (hunk, old_count, new_count) = parse_hunk_header(line)?;
+                    hunks.push(hunk);
+                    old = old_count;
+                    new = new_count;
+                }
+                continue;
+            }
+            let hunk = hunks.last_mut().unwrap();
+            let invalid = || Error::new(&format!("Invalid patch line `{}`.", line));
+            match line.chars().next() {
+                Some(' ') | None if old > 0 && new > 0 => {
+                    hunk.lines.push(Line::Context(line.get(1..).unwrap_or_default().to_string()));
+                    old -= 1;
+                    new -= 1;
+                }
+                Some('-') if old > 0 => {
+                    hunk.lines.push(Line::Remove(line[1..].to_string()));
+                    old -= 1;
+                }
+                Some('+') if new > 0 => {
+                    hunk.lines.push(Line::Add(line[1..].to_string()));
+                    new -= 1;
+                }
+                Some('\\') => {}
+                _ => return Err(invalid()),
+            }
+        }
+        if old > 0 || new > 0 {
+            return Err(Error::new(&format!("Hunk #{} is truncated.", hunks.len())));
+        }
+        if hunks.is_empty() {
+            return Err(Error::new("The patch has no hunks."));
+        }
+        return Ok(Patch { hunks });
+    }
+
+    /// The patch that undoes this one.
+    pub fn reverse(&self) -> Patch {
+        return Patch {
+            hunks: self
+                .hunks
+                .iter()
+                .map(|hunk| Hunk {
+                    old_start: hunk.new_start,
+                    new_start: hunk.old_start,
+                    lines: hunk
+                        .lines
+                        .iter()
+                        .map(|line| match line {
+                            Line::Context(text) => Line::Context(text.clone()),
+                            Line::Remove(text) => Line::Add(text.clone()),
+                            Line::Add(text) => Line::Remove(text.clone()),
+                        })
+                        .collect(),
+                })
+                .collect(),
+        };
+    }
+
+    /// Apply the patch, allowing hunks to move and up to `MAX_FUZZ` context lines to mismatch.
+    pub fn apply(&self, content: &str) -> Result<(String, Vec<HunkResult>)> {
+        return self.apply_with_fuzz(content, MAX_FUZZ);
+    }
+
+    /// Apply the patch only if every hunk matches with all of its context.
+    pub fn apply_exactly(&self, content: &str) -> Result<String> {
+        return self.apply_with_fuzz(content, 0).map(|(content, _)| content);
+    }
+
+    fn apply_with_fuzz(&self, content: &str, max_fuzz: usize) -> Result<(String, Vec<HunkResult>)> {
+        let mut lines: Vec<String> = content.lines().map(|line| line.to_string()).collect();
+        let mut results = vec![];
+        let mut delta: isize = 0;
+        let mut minimum = 0;
+        for (index, hunk) in self.hunks.iter().enumerate() {
+            let expected = (hunk.old_start.max(1) as isize - 1 + delta).max(0) as usize;
+            let (position, fuzz, old, new) = (0..=max_fuzz)
+                .filter_map(|fuzz| {
+                    let (old, new) = hunk.trimmed(fuzz)?;
+                    let position = find(&lines, &old, expected + fuzz.min(hunk.leading_context()), minimum)?;
+                    Some((position, fuzz, old, new))
+                })
+                .next()
+                .ok_or_else(|| Error::new(&format!("Hunk #{} doesn't apply.", index + 1)))?;
+            let trimmed = fuzz.min(hunk.leading_context());
+            results.push(HunkResult {
+                line: position + 1,
+                offset: position as isize - trimmed as isize - expected as isize,
+                fuzz,
+            });
+            lines.splice(position..position + old.len(), new.iter().cloned());
+            delta += new.len() as isize - old.len() as isize;
+            minimum = position + new.len();
+        }
+        let mut result = lines.join("\n");
+        if !lines.is_empty() && (content.ends_with('\n') || content.is_empty()) {
+            result.push('\n');
+        }
+        return Ok((result, results));
+    }
+}
+
+impl Hunk {
+    fn leading_context(&self) -> usize {
+        return self.lines.iter().take_while(|line| matches!(line, Line::Context(_))).count();
+    }
+
+    fn trailing_context(&self) -> usize {
+        return self.lines.iter().rev().take_while(|line| matches!(line, Line::Context(_))).count();
+    }
+
+    /// Old and new lines of the hunk without `fuzz` context lines at either end.
+    fn trimmed(&self, fuzz: usize) -> Option<(Vec<String>, Vec<String>)> {
+        if fuzz > 0 && self.leading_context() < fuzz && self.trailing_context() < fuzz {
+            return None;
+        }
+        let start = fuzz.min(self.leading_context());
+        let end = self.lines.len() - fuzz.min(self.trailing_context());
+        let mut old = vec![];
+        let mut new = vec![];
+        for line in &self.lines[start..end.max(start)] {
+            match line {
+                Line::Context(text) => {
+                    old.push(text.clone());
+                    new.push(text.clone());
+                }
+                Line::Remove(text) => old.push(text.clone()),
+                Line::Add(text) => new.push(text.clone()),
+            }
+        }
+        return Some((old, new));
+    }
+}
+
+/// Find `needle` in `lines` at or after `minimum`, closest to `expected`.
+fn find(lines: &[String], needle: &[String], expected: usize, minimum: usize) -> Option<usize> {
+    if needle.is_empty() {
+        return Some(expected.clamp(minimum, lines.len().max(minimum)));
+    }
+    if needle.len() > lines.len() {
+        return None;
+    }
+    let last = lines.len() - needle.len();
+    let matches = |position: usize| position >= minimum && position <= last && lines[position..position + needle.len()] == *needle;
+    for distance in 0..=lines.len() {
+        if expected + distance <= last && matches(expected + distance) {
+            return Some(expected + distance);
+        }
+        if distance <= expected && matches(expected - distance) {
+            return Some(expected - distance);
+        }
+    }
+    return None;
+}
+
+/// An empty hunk and its old and new line counts, from `@@ -start,count +start,count @@`. A
+/// count that is left out is 1.
+fn parse_hunk_header(line: &str) -> Result<(Hunk, usize, usize)> {
+    let invalid = || Error::new(&format!("Invalid hunk header `{}`.", line));
+    let mut parts = line.trim_start_matches("@@").split_whitespace();
+    let old = parts.next().and_then(|part| part.strip_prefix('-')).ok_or_else(invalid)?;
+    let new = parts.next().and_then(|part| part.strip_prefix('+')).ok_or_else(invalid)?;
+    let range = |range: &str| -> Result<(usize, usize)> {
+        let mut numbers = range.splitn(2, ',');
+        let start = numbers.next().unwrap_or("").parse::<usize>().map_err(|_| invalid())?;
+        let count = match numbers.next() {
+            Some(count) => count.parse::<usize>().map_err(|_| invalid())?,
+            None => 1,
+        };
+        return Ok((start, count));
+    };
+    let (old_start, old_count) = range(old)?;
+    let (new_start, new_count) = range(new)?;
+    let hunk = Hunk {
+        old_start,
+        new_start,
+        lines: vec![],
+    };
+    return Ok((hunk, old_count, new_count));
+}
+
+impl fmt::Display for Patch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for hunk in &self.hunks {
+            let old = hunk.lines.iter().filter(|line| !matches!(line, Line::Add(_))).count();
+            let new = hunk.lines.iter().filter(|line| !matches!(line, Line::Remove(_))).count();
+            writeln!(f, "@@ -{},{} +{},{} @@", hunk.old_start, old, hunk.new_start, new)?;
+            for line in &hunk.lines {
+                match line {
+                    Line::Context(text) => writeln!(f, " {}", text)?,
+                    Line::Remove(text) => writeln!(f, "-{}", text)?,
+                    Line::Add(text) => writeln!(f, "+{}", text)?,
+                }
+            }
+        }
+        return Ok(());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LUA: &str = "local a = 1\n-- old comment\nlocal b = 2\n";
+
+    const LUA_PATCH: &str = "\
+--- a/init.lua
++++ b/init.lua
+@@ -1,3 +1,3 @@
+ local a = 1
+--- old comment
++-- new comment
+ local b = 2
+";
+
+    #[test]
+    fn parse_reads_removed_lines_that_look_like_headers() {
+        let patch = Patch::parse(LUA_PATCH).unwrap();
+        assert_eq!(
+            patch.hunks[0].lines,
+            vec![
+                Line::Context("local a = 1".to_string()),
+                Line::Remove("-- old comment".to_string()),
+                Line::Add("-- new comment".to_string()),
+                Line::Context("local b = 2".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_skips_headers_between_hunks() {
+        let content = "\
+diff --git a/x b/x
+index 0000000..1111111 100644
+--- a/x
++++ b/x
+@@ -1 +1 @@
+-a
++b
+@@ -5,2 +5,2 @@
+ e
+-f
++g
+\\ No newline at end of file
+";
+        let patch = Patch::parse(content).unwrap();
+        assert_eq!(patch.hunks.len(), 2);
+        assert_eq!(patch.hunks[0].lines, vec![Line::Remove("a".to_string()), Line::Add("b".to_string())]);
+        assert_eq!(patch.hunks[1].old_start, 5);
+        assert_eq!(patch.hunks[1].lines.len(), 3);
+    }
+
+    #[test]
+    fn parse_reads_empty_lines_as_context() {
+        let patch = Patch::parse("@@ -1,3 +1,3 @@\n a\n\n-b\n+c\n").unwrap();
+        assert_eq!(patch.hunks[0].lines[1], Line::Context(String::new()));
+    }
+
+    #[test]
+    fn parse_fails_on_truncated_hunks() {
+        assert!(Patch::parse("@@ -1,3 +1,3 @@\n a\n-b\n").is_err());
+    }
+
+    #[test]
+    fn parse_fails_on_lines_beyond_the_counts() {
+        assert!(Patch::parse("@@ -1,1 +1,1 @@\n-a\n-b\n+c\n").is_err());
+    }
+
+    #[test]
+    fn parse_fails_without_hunks() {
+        assert!(Patch::parse("--- a/x\n+++ b/x\n").is_err());
+        assert!(Patch::parse("@@ -1 +1 @\n").is_err());
+    }
+
+    #[test]
+    fn apply_replaces_removed_lines() {
+        let patch = Patch::parse(LUA_PATCH).unwrap();
+        let (patched, results) = patch.apply(LUA).unwrap();
+        assert_eq!(patched, "local a = 1\n-- new comment\nlocal b = 2\n");
+        assert_eq!(results, vec![HunkResult { line: 1, offset: 0, fuzz: 0 }]);
+    }
+
+    #[test]
+    fn apply_finds_moved_hunks() {
+        let patch = Patch::parse(LUA_PATCH).unwrap();
+        let (patched, results) = patch.apply(&format!("-- header\n\n{}", LUA)).unwrap();
+        assert_eq!(patched, "-- header\n\nlocal a = 1\n-- new comment\nlocal b = 2\n");
+        assert_eq!(results[0].offset, 2);
+        assert_eq!(results[0].fuzz, 0);
+    }
+
+    #[test]
+    fn apply_ignores_changed_context_with_fuzz() {
+        let patch = Patch::parse("@@ -1,5 +1,5 @@\n a\n b\n-c\n+C\n d\n e\n").unwrap();
+        let (patched, results) = patch.apply("x\nb\nc\nd\ny\n").unwrap();
+        assert_eq!(patched, "x\nb\nC\nd\ny\n");
+        assert_eq!(results[0].fuzz, 1);
+        assert!(patch.apply_exactly("x\nb\nc\nd\ny\n").is_err());
+    }
+
+    #[test]
+    fn apply_fails_when_a_hunk_does_not_match() {
+        let patch = Patch::parse(LUA_PATCH).unwrap();
+        assert!(patch.apply("something\nelse\nentirely\n").is_err());
+    }
+
+    #[test]
+    fn reverse_undoes_the_patch() {
+        let patch = Patch::parse(LUA_PATCH).unwrap();
+        let (patched, _) = patch.apply(LUA).unwrap();
+        assert_eq!(patch.reverse().apply_exactly(&patched).unwrap(), LUA);
+        assert_eq!(patch.reverse().reverse(), patch);
+    }
+
+    #[test]
+    fn display_parses_back() {
+        let patch = Patch::parse(LUA_PATCH).unwrap();
+        assert_eq!(Patch::parse(&patch.to_string()).unwrap(), patch);
+        assert_eq!(Patch::parse(&patch.reverse().to_string()).unwrap(), patch.reverse());
+    }
+}
@@ -0,0 +1,170 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+
+use serde::Serialize;
+
+use crate::error::{Error, Result};
+
+/// Information about the machine idot is running on.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(transparent)]
+pub struct Facts {
+    values: BTreeMap<String, String>,
+}
+
+impl Facts {
+    pub fn new() -> Self {
+        return Facts {
+            values: BTreeMap::new(),
+        };
+    }
+
+    pub fn collect() -> Self {
+        let mut facts = Facts::new();
+        let uid = fs::metadata("/proc/self").map(|metadata| metadata.uid()).ok();
+        let os_release = read_os_release();
+        let kernel = read_trimmed("/proc/sys/kernel/osrelease").unwrap_or_default();
+
+        facts.insert("hostname", &detect_hostname());
+        facts.insert("username", &detect_username(uid));
+        facts.insert("uid", &uid.map(|uid| uid.to_string()).unwrap_or_default());
+        facts.insert("os", env::consts::OS);
+        facts.insert("distro_id", os_release.get("ID").map(|s| s.as_str()).unwrap_or(""));
+        facts.insert(
+            "distro_version",
+            os_release.get("VERSION_ID").map(|s| s.as_str()).unwrap_or(""),
+        );
+        facts.insert("kernel", &kernel);
+        facts.insert("arch", env::consts::ARCH);
+        facts.insert("wsl", &detect_wsl(&kernel).to_string());
+        facts.insert("container", &detect_container());
+        facts.insert("shells", &detect_shells().join(","));
+        return facts;
+    }
+
+    /// Replace a fact with a `key=value` pair given on the command line.
+    pub fn with_override(mut self, assignment: &str) -> Result<Self> {
+        let mut parts = assignment.splitn(2, '=');
+        let key = parts.next().unwrap_or("").trim();
+        let value = parts
+            .next()
+            .ok_or_else(|| Error::new(&format!("Invalid fact `{}`, expected `key=value`.", assignment)))?;
+        if key.is_empty() {
+            return Err(Error::new(&format!("Invalid fact `{}`, expected `key=value`.", assignment)));
+        }
+        self.insert(key, value);
+        return Ok(self);
+    }
+
+    pub fn insert(&mut self, key: &str, value: &str) {
+        self.values.insert(key.to_string(), value.to_string());
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        return self.values.get(key).map(|value| value.as_str());
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &String)> {
+        return self.values.iter();
+    }
+}
+
+impl Default for Facts {
+    fn default() -> Self {
+        return Facts::new();
+    }
+}
+
+fn read_trimmed<P: AsRef<Path>>(path: P) -> Option<String> {
+    return fs::read_to_string(path).ok().map(|content| content.trim().to_string());
+}
+
+fn read_os_release() -> BTreeMap<String, String> {
+    let content = fs::read_to_string("/etc/os-release")
+        .or_else(|_| fs::read_to_string("/usr/lib/os-release"))
+        .unwrap_or_default();
+    return parse_os_release(&content);
+}
+
+fn parse_os_release(content: &str) -> BTreeMap<String, String> {
+    let mut values = BTreeMap::new();
+    for line in content.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if let Some(index) = line.find('=') {
+            let key = line[..index].trim();
+            let value = line[index + 1..].trim().trim_matches(|c| c == '"' || c == '\'');
+            values.insert(key.to_string(), value.to_string());
+        }
+    }
+    return values;
+}
+
+fn detect_hostname() -> String {
+    return read_trimmed("/proc/sys/kernel/hostname")
+        .or_else(|| read_trimmed("/etc/hostname"))
+        .or_else(|| env::var("HOSTNAME").ok())
+        .unwrap_or_default();
+}
+
+fn detect_username(uid: Option<u32>) -> String {
+    if let Some(uid) = uid {
+        let passwd = fs::read_to_string("/etc/passwd").unwrap_or_default();
+        for line in passwd.lines() {
+            let fields: Vec<&str> = line.split(':').collect();
+            if fields.len() > 2 && fields[2] == uid.to_string() {
+                return fields[0].to_string();
+            }
+        }
+    }
+    return env::var("USER")
+        .or_else(|_| env::var("LOGNAME"))
+        .unwrap_or_default();
+}
+
+fn detect_wsl(kernel: &str) -> bool {
+    return env::var("WSL_DISTRO_NAME").is_ok() || kernel.to_lowercase().contains("microsoft");
+}
+
+fn detect_container() -> String {
+    if let Ok(container) = env::var("container") {
+        if !container.is_empty() {
+            return container;
+        }
+    }
+    if Path::new("/.dockerenv").exists() {
+        return "docker".to_string();
+    }
+    if Path::new("/run/.containerenv").exists() {
+        return "podman".to_string();
+    }
+    let cgroup = fs::read_to_string("/proc/1/cgroup").unwrap_or_default();
+    for name in &["docker", "kubepods", "lxc", "containerd"] {
+        if cgroup.contains(name) {
+            return name.to_string();
+        }
+    }
+    return String::new();
+}
+
+fn detect_shells() -> Vec<String> {
+    let content = fs::read_to_string("/etc/shells").unwrap_or_default();
+    let mut shells: Vec<String> = vec![];
+    for line in content.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || !Path::new(line).exists() {
+            continue;
+        }
+        if let Some(name) = Path::new(line).file_name().and_then(|name| name.to_str()) {
+            if !shells.iter().any(|shell| shell == name) {
+                shells.push(name.to_string());
+            }
+        }
+    }
+    return shells;
+}
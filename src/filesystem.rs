@@ -0,0 +1,710 @@
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::fs;
+use std::io::{Error, ErrorKind, Result};
+use std::os::unix::fs::{MetadataExt, PermissionsExt};
+use std::path::{Component, Path, PathBuf};
+use std::sync::Mutex;
+
+/// The file operations of link logic, so links can be deployed somewhere else than on disk.
+///
+/// Paths are absolute. Like `std::fs`, `metadata` and `canonicalize` follow symbolic links and
+/// `symlink_metadata`, `read_link` and `remove` don't.
+pub trait Filesystem: Send + Sync {
+    fn metadata(&self, path: &Path) -> Result<Metadata>;
+    fn symlink_metadata(&self, path: &Path) -> Result<Metadata>;
+    fn read_link(&self, path: &Path) -> Result<PathBuf>;
+    /// Create a symbolic link at `path` whose content is `content`.
+    fn symlink(&self, content: &Path, path: &Path) -> Result<()>;
+    /// Create a hard link at `path` to the file `target`.
+    fn hard_link(&self, target: &Path, path: &Path) -> Result<()>;
+    /// Remove a file, a symbolic link or a whole directory.
+    fn remove(&self, path: &Path) -> Result<()>;
+    /// Create a directory and its missing parents.
+    fn mkdir(&self, path: &Path) -> Result<()>;
+    fn rename(&self, from: &Path, to: &Path) -> Result<()>;
+    fn read(&self, path: &Path) -> Result<Vec<u8>>;
+    /// Create or truncate a file.
+    fn write(&self, path: &Path, content: &[u8]) -> Result<()>;
+    /// The children of a directory, sorted.
+    fn read_dir(&self, path: &Path) -> Result<Vec<PathBuf>>;
+    fn set_permissions(&self, path: &Path, mode: u32) -> Result<()>;
+    fn canonicalize(&self, path: &Path) -> Result<PathBuf>;
+
+    fn exists(&self, path: &Path) -> bool {
+        return self.metadata(path).is_ok();
+    }
+
+    /// Whether anything is at `path`, a dangling symbolic link too.
+    fn actually_exists(&self, path: &Path) -> bool {
+        return self.symlink_metadata(path).is_ok();
+    }
+
+    fn is_symbolic(&self, path: &Path) -> bool {
+        return self.symlink_metadata(path).map(|metadata| metadata.is_symlink()).unwrap_or_default();
+    }
+
+    fn is_dir(&self, path: &Path) -> bool {
+        return self.metadata(path).map(|metadata| metadata.is_dir()).unwrap_or_default();
+    }
+
+    fn is_file(&self, path: &Path) -> bool {
+        return self.metadata(path).map(|metadata| metadata.is_file()).unwrap_or_default();
+    }
+
+    fn read_to_string(&self, path: &Path) -> Result<String> {
+        return String::from_utf8(self.read(path)?).map_err(|e| Error::new(ErrorKind::InvalidData, e));
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileType {
+    File,
+    Directory,
+    Symlink,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Metadata {
+    pub file_type: FileType,
+    /// Permission bits, e.g. `0o644`.
+    pub mode: u32,
+    pub len: u64,
+    /// The device and inode, equal for two paths of the same file.
+    pub device: u64,
+    pub inode: u64,
+}
+
+impl Metadata {
+    pub fn is_file(&self) -> bool {
+        return self.file_type == FileType::File;
+    }
+
+    pub fn is_dir(&self) -> bool {
+        return self.file_type == FileType::Directory;
+    }
+
+    pub fn is_symlink(&self) -> bool {
+        return self.file_type == FileType::Symlink;
+    }
+}
+
+/// The filesystem of the machine, through `std::fs`.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct RealFilesystem;
+
+impl RealFilesystem {
+    fn convert(metadata: fs::Metadata) -> Metadata {
+        let file_type = if metadata.file_type().is_symlink() {
+            FileType::Symlink
+        } else if metadata.is_dir() {
+            FileType::Directory
+        } else {
+            FileType::File
+        };
+        return Metadata {
+            file_type,
+            mode: metadata.permissions().mode() & 0o7777,
+            len: metadata.len(),
+            device: metadata.dev(),
+            inode: metadata.ino(),
+        };
+    }
+}
+
+impl Filesystem for RealFilesystem {
+    fn metadata(&self, path: &Path) -> Result<Metadata> {
+        return fs::metadata(path).map(RealFilesystem::convert);
+    }
+
+    fn symlink_metadata(&self, path: &Path) -> Result<Metadata> {
+        return fs::symlink_metadata(path).map(RealFilesystem::convert);
+    }
+
+    fn read_link(&self, path: &Path) -> Result<PathBuf> {
+        return fs::read_link(path);
+    }
+
+    fn symlink(&self, content: &Path, path: &Path) -> Result<()> {
+        return std::os::unix::fs::symlink(content, path);
+    }
+
+    fn hard_link(&self, target: &Path, path: &Path) -> Result<()> {
+        return fs::hard_link(target, path);
+    }
+
+    fn remove(&self, path: &Path) -> Result<()> {
+        if fs::symlink_metadata(path)?.is_dir() {
+            return fs::remove_dir_all(path);
+        }
+        return fs::remove_file(path);
+    }
+
+    fn mkdir(&self, path: &Path) -> Result<()> {
+        return fs::create_dir_all(path);
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> Result<()> {
+        return fs::rename(from, to);
+    }
+
+    fn read(&self, path: &Path) -> Result<Vec<u8>> {
+        return fs::read(path);
+    }
+
+    fn write(&self, path: &Path, content: &[u8]) -> Result<()> {
+        return fs::write(path, content);
+    }
+
+    fn read_dir(&self, path: &Path) -> Result<Vec<PathBuf>> {
+        let mut entries = fs::read_dir(path)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<Result<Vec<PathBuf>>>()?;
+        entries.sort();
+        return Ok(entries);
+    }
+
+    fn set_permissions(&self, path: &Path, mode: u32) -> Result<()> {
+        return fs::set_permissions(path, fs::Permissions::from_mode(mode));
+    }
+
+    fn canonicalize(&self, path: &Path) -> Result<PathBuf> {
+        return fs::canonicalize(path);
+    }
+}
+
+/// A filesystem that only lives in memory, for tests and dry runs. It has the root directory
+/// and what is added to it.
+///
+/// It models symbolic links, relative or absolute and dangling or looping, hard links sharing
+/// one inode, and the owner bits of permissions: reading needs `0o400`, and changing a file or
+/// the entries of a directory needs `0o200`. Everything is on device `1`. Errors are the ones
+/// `std::fs` returns on Linux, and `fail` makes every operation on a path fail.
+#[derive(Debug)]
+pub struct MemoryFilesystem {
+    tree: Mutex<Tree>,
+}
+
+/// What a path is, files refer to their content by inode so hard links share it.
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+    File(u64),
+    Directory { inode: u64, mode: u32 },
+    Symlink { inode: u64, content: PathBuf },
+}
+
+#[derive(Debug)]
+struct Data {
+    content: Vec<u8>,
+    mode: u32,
+}
+
+#[derive(Debug)]
+struct Tree {
+    nodes: BTreeMap<PathBuf, Node>,
+    files: BTreeMap<u64, Data>,
+    next_inode: u64,
+    failures: Vec<(PathBuf, ErrorKind)>,
+}
+
+/// Symbolic links followed while resolving a path before it fails, as on Linux.
+const MAX_SYMLINKS: usize = 40;
+
+impl Default for MemoryFilesystem {
+    fn default() -> Self {
+        return MemoryFilesystem::new();
+    }
+}
+
+impl MemoryFilesystem {
+    pub fn new() -> Self {
+        let mut nodes = BTreeMap::new();
+        nodes.insert(PathBuf::from("/"), Node::Directory { inode: 1, mode: 0o755 });
+        return MemoryFilesystem {
+            tree: Mutex::new(Tree {
+                nodes,
+                files: BTreeMap::new(),
+                next_inode: 2,
+                failures: vec![],
+            }),
+        };
+    }
+
+    /// Add a file with mode `0o644`, and its missing parents.
+    pub fn add_file<P: AsRef<Path>>(&self, path: P, content: &[u8]) -> Result<()> {
+        let path = path.as_ref();
+        if let Some(parent) = path.parent() {
+            self.mkdir(parent)?;
+        }
+        return self.write(path, content);
+    }
+
+    pub fn add_dir<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        return self.mkdir(path.as_ref());
+    }
+
+    /// Add a symbolic link to `content`, and the missing parents of `path`.
+    pub fn add_symlink<P: AsRef<Path>, Q: AsRef<Path>>(&self, path: P, content: Q) -> Result<()> {
+        let path = path.as_ref();
+        if let Some(parent) = path.parent() {
+            self.mkdir(parent)?;
+        }
+        return self.symlink(content.as_ref(), path);
+    }
+
+    /// Make every operation on `path` fail with `kind`, e.g. to simulate a full disk.
+    pub fn fail<P: AsRef<Path>>(&self, path: P, kind: ErrorKind) {
+        self.tree.lock().unwrap().failures.push((path.as_ref().to_path_buf(), kind));
+    }
+
+    /// Every path with what is there, for checking the result of a run.
+    pub fn paths(&self) -> Vec<(PathBuf, FileType)> {
+        let tree = self.tree.lock().unwrap();
+        return tree.nodes.iter().map(|(path, node)| (path.clone(), node.file_type())).collect();
+    }
+}
+
+impl Node {
+    fn file_type(&self) -> FileType {
+        return match self {
+            Node::File(_) => FileType::File,
+            Node::Directory { .. } => FileType::Directory,
+            Node::Symlink { .. } => FileType::Symlink,
+        };
+    }
+}
+
+fn error(kind: ErrorKind, message: &str, path: &Path) -> Error {
+    return Error::new(kind, format!("{}: {}", message, path.to_str().unwrap()));
+}
+
+impl Tree {
+    fn check(&self, path: &Path) -> Result<()> {
+        return match self.failures.iter().find(|(failing, _)| failing == path) {
+            Some((_, kind)) => Err(error(*kind, "Injected failure", path)),
+            None => Ok(()),
+        };
+    }
+
+    fn allocate_inode(&mut self) -> u64 {
+        self.next_inode += 1;
+        return self.next_inode - 1;
+    }
+
+    fn metadata(&self, node: &Node) -> Metadata {
+        let (file_type, inode, mode, len) = match node {
+            Node::File(inode) => {
+                let data = &self.files[inode];
+                (FileType::File, *inode, data.mode, data.content.len() as u64)
+            }
+            Node::Directory { inode, mode } => (FileType::Directory, *inode, *mode, 0),
+            Node::Symlink { inode, content } => (FileType::Symlink, *inode, 0o777, content.as_os_str().len() as u64),
+        };
+        return Metadata {
+            file_type,
+            mode,
+            len,
+            device: 1,
+            inode,
+        };
+    }
+
+    /// The path of what `path` refers to, with the symbolic links in its parents resolved, and
+    /// the last one too with `follow`. The resolved path may not exist, its parent does.
+    fn resolve(&self, path: &Path, follow: bool) -> Result<PathBuf> {
+        self.check(path)?;
+        if !path.is_absolute() {
+            return Err(error(ErrorKind::InvalidInput, "Not an absolute path", path));
+        }
+        let mut pending: Vec<OsString> = vec![];
+        push_components(&mut pending, path);
+        let mut resolved = PathBuf::from("/");
+        let mut followed = 0;
+        while let Some(name) = pending.pop() {
+            if name == "." {
+                continue;
+            }
+            if name == ".." {
+                resolved.pop();
+                continue;
+            }
+            let candidate = resolved.join(&name);
+            let last = pending.iter().all(|name| name == ".");
+            match self.nodes.get(&candidate) {
+                Some(Node::Symlink { content, .. }) if !last || follow => {
+                    followed += 1;
+                    if followed > MAX_SYMLINKS {
+                        return Err(error(ErrorKind::Other, "Too many levels of symbolic links", path));
+                    }
+                    if content.is_absolute() {
+                        resolved = PathBuf::from("/");
+                    }
+                    push_components(&mut pending, content);
+                }
+                Some(Node::File(_)) if !last => {
+                    return Err(error(ErrorKind::NotADirectory, "Not a directory", path));
+                }
+                None if !last => return Err(error(ErrorKind::NotFound, "No such file or directory", path)),
+                _ => resolved = candidate,
+            }
+        }
+        return Ok(resolved);
+    }
+
+    fn node(&self, path: &Path, follow: bool) -> Result<(PathBuf, &Node)> {
+        let resolved = self.resolve(path, follow)?;
+        return match self.nodes.get(&resolved) {
+            Some(node) => Ok((resolved, node)),
+            None => Err(error(ErrorKind::NotFound, "No such file or directory", path)),
+        };
+    }
+
+    /// Fail unless the entries of the directory `resolved` is in can be changed.
+    fn writable_parent(&self, resolved: &Path, path: &Path) -> Result<()> {
+        let parent = match resolved.parent() {
+            Some(parent) => parent,
+            None => return Err(error(ErrorKind::PermissionDenied, "Permission denied", path)),
+        };
+        return match self.nodes.get(parent) {
+            Some(Node::Directory { mode, .. }) if mode & 0o200 != 0 => Ok(()),
+            Some(Node::Directory { .. }) => Err(error(ErrorKind::PermissionDenied, "Permission denied", path)),
+            Some(_) => Err(error(ErrorKind::NotADirectory, "Not a directory", path)),
+            None => Err(error(ErrorKind::NotFound, "No such file or directory", path)),
+        };
+    }
+
+    /// Fail unless nothing is at `resolved` and it can be created.
+    fn creatable(&self, resolved: &Path, path: &Path) -> Result<()> {
+        if self.nodes.contains_key(resolved) {
+            return Err(error(ErrorKind::AlreadyExists, "File exists", path));
+        }
+        return self.writable_parent(resolved, path);
+    }
+
+    fn mkdir(&mut self, path: &Path) -> Result<()> {
+        match self.node(path, true) {
+            Ok((_, Node::Directory { .. })) => return Ok(()),
+            Ok(_) => return Err(error(ErrorKind::AlreadyExists, "File exists", path)),
+            Err(ref e) if e.kind() == ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+        if let Some(parent) = path.parent() {
+            self.mkdir(parent)?;
+        }
+        let resolved = self.resolve(path, true)?;
+        self.creatable(&resolved, path)?;
+        let inode = self.allocate_inode();
+        self.nodes.insert(resolved, Node::Directory { inode, mode: 0o755 });
+        return Ok(());
+    }
+
+    /// Drop the content of files no path links to anymore.
+    fn collect_garbage(&mut self) {
+        let nodes = &self.nodes;
+        self.files.retain(|inode, _| nodes.values().any(|node| *node == Node::File(*inode)));
+    }
+}
+
+/// Push the components of `path` so that the first one is popped first.
+fn push_components(pending: &mut Vec<OsString>, path: &Path) {
+    for component in path.components().rev() {
+        match component {
+            Component::Normal(name) => pending.push(name.to_os_string()),
+            Component::CurDir => pending.push(OsString::from(".")),
+            Component::ParentDir => pending.push(OsString::from("..")),
+            Component::RootDir | Component::Prefix(_) => {}
+        }
+    }
+}
+
+impl Filesystem for MemoryFilesystem {
+    fn metadata(&self, path: &Path) -> Result<Metadata> {
+        let tree = self.tree.lock().unwrap();
+        return tree.node(path, true).map(|(_, node)| tree.metadata(node));
+    }
+
+    fn symlink_metadata(&self, path: &Path) -> Result<Metadata> {
+        let tree = self.tree.lock().unwrap();
+        return tree.node(path, false).map(|(_, node)| tree.metadata(node));
+    }
+
+    fn read_link(&self, path: &Path) -> Result<PathBuf> {
+        let tree = self.tree.lock().unwrap();
+        return match tree.node(path, false)? {
+            (_, Node::Symlink { content, .. }) => Ok(content.clone()),
+            _ => Err(error(ErrorKind::InvalidInput, "Not a symbolic link", path)),
+        };
+    }
+
+    fn symlink(&self, content: &Path, path: &Path) -> Result<()> {
+        let mut tree = self.tree.lock().unwrap();
+        let resolved = tree.resolve(path, false)?;
+        tree.creatable(&resolved, path)?;
+        let inode = tree.allocate_inode();
+        tree.nodes.insert(
+            resolved,
+            Node::Symlink {
+                inode,
+                content: content.to_path_buf(),
+            },
+        );
+        return Ok(());
+    }
+
+    fn hard_link(&self, target: &Path, path: &Path) -> Result<()> {
+        let mut tree = self.tree.lock().unwrap();
+        let node = match tree.node(target, false)? {
+            (_, Node::Directory { .. }) => return Err(error(ErrorKind::PermissionDenied, "Operation not permitted", target)),
+            (_, node) => node.clone(),
+        };
+        let resolved = tree.resolve(path, false)?;
+        tree.creatable(&resolved, path)?;
+        tree.nodes.insert(resolved, node);
+        return Ok(());
+    }
+
+    fn remove(&self, path: &Path) -> Result<()> {
+        let mut tree = self.tree.lock().unwrap();
+        let (resolved, _) = tree.node(path, false)?;
+        tree.writable_parent(&resolved, path)?;
+        tree.nodes.retain(|existing, _| !existing.starts_with(&resolved));
+        tree.collect_garbage();
+        return Ok(());
+    }
+
+    fn mkdir(&self, path: &Path) -> Result<()> {
+        return self.tree.lock().unwrap().mkdir(path);
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> Result<()> {
+        let mut tree = self.tree.lock().unwrap();
+        let (source, _) = tree.node(from, false)?;
+        tree.writable_parent(&source, from)?;
+        let destination = tree.resolve(to, false)?;
+        tree.writable_parent(&destination, to)?;
+        if destination.starts_with(&source) && destination != source {
+            return Err(error(ErrorKind::InvalidInput, "Invalid argument", to));
+        }
+        if let Some(Node::Directory { .. }) = tree.nodes.get(&destination) {
+            if tree.nodes.keys().any(|path| path.parent() == Some(&destination)) {
+                return Err(error(ErrorKind::DirectoryNotEmpty, "Directory not empty", to));
+            }
+        }
+        tree.nodes.retain(|existing, _| !existing.starts_with(&destination) || existing.starts_with(&source));
+        let moved: Vec<PathBuf> = tree.nodes.keys().filter(|path| path.starts_with(&source)).cloned().collect();
+        for path in moved {
+            let node = tree.nodes.remove(&path).unwrap();
+            let path = destination.join(path.strip_prefix(&source).unwrap());
+            tree.nodes.insert(path, node);
+        }
+        tree.collect_garbage();
+        return Ok(());
+    }
+
+    fn read(&self, path: &Path) -> Result<Vec<u8>> {
+        let tree = self.tree.lock().unwrap();
+        return match tree.node(path, true)? {
+            (_, Node::File(inode)) => match &tree.files[inode] {
+                data if data.mode & 0o400 != 0 => Ok(data.content.clone()),
+                _ => Err(error(ErrorKind::PermissionDenied, "Permission denied", path)),
+            },
+            _ => Err(error(ErrorKind::IsADirectory, "Is a directory", path)),
+        };
+    }
+
+    fn write(&self, path: &Path, content: &[u8]) -> Result<()> {
+        let mut tree = self.tree.lock().unwrap();
+        let resolved = tree.resolve(path, true)?;
+        match tree.nodes.get(&resolved) {
+            Some(Node::File(inode)) => {
+                let inode = *inode;
+                let data = tree.files.get_mut(&inode).unwrap();
+                if data.mode & 0o200 == 0 {
+                    return Err(error(ErrorKind::PermissionDenied, "Permission denied", path));
+                }
+                data.content = content.to_vec();
+            }
+            Some(_) => return Err(error(ErrorKind::IsADirectory, "Is a directory", path)),
+            None => {
+                tree.writable_parent(&resolved, path)?;
+                let inode = tree.allocate_inode();
+                tree.files.insert(
+                    inode,
+                    Data {
+                        content: content.to_vec(),
+                        mode: 0o644,
+                    },
+                );
+                tree.nodes.insert(resolved, Node::File(inode));
+            }
+        }
+        return Ok(());
+    }
+
+    fn read_dir(&self, path: &Path) -> Result<Vec<PathBuf>> {
+        let tree = self.tree.lock().unwrap();
+        let resolved = match tree.node(path, true)? {
+            (resolved, Node::Directory { mode, .. }) if mode & 0o400 != 0 => resolved,
+            (_, Node::Directory { .. }) => return Err(error(ErrorKind::PermissionDenied, "Permission denied", path)),
+            _ => return Err(error(ErrorKind::NotADirectory, "Not a directory", path)),
+        };
+        return Ok(tree
+            .nodes
+            .keys()
+            .filter(|child| child.parent() == Some(&resolved))
+            .map(|child| path.join(child.file_name().unwrap()))
+            .collect());
+    }
+
+    fn set_permissions(&self, path: &Path, mode: u32) -> Result<()> {
+        let mut tree = self.tree.lock().unwrap();
+        let (resolved, node) = tree.node(path, true)?;
+        let mode = mode & 0o7777;
+        match node.clone() {
+            Node::File(inode) => tree.files.get_mut(&inode).unwrap().mode = mode,
+            Node::Directory { inode, .. } => {
+                tree.nodes.insert(resolved, Node::Directory { inode, mode });
+            }
+            Node::Symlink { .. } => {}
+        }
+        return Ok(());
+    }
+
+    fn canonicalize(&self, path: &Path) -> Result<PathBuf> {
+        let tree = self.tree.lock().unwrap();
+        return tree.node(path, true).map(|(resolved, _)| resolved);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kind<T: std::fmt::Debug>(result: Result<T>) -> ErrorKind {
+        return result.unwrap_err().kind();
+    }
+
+    #[test]
+    fn symlinks_resolve_relative_and_absolute_contents() {
+        let filesystem = MemoryFilesystem::new();
+        filesystem.add_file("/dotfiles/vim/vimrc", b"set number").unwrap();
+        filesystem.add_symlink("/home/.vimrc", "../dotfiles/vim/vimrc").unwrap();
+        filesystem.add_symlink("/home/vim", "/dotfiles/vim").unwrap();
+        assert_eq!(filesystem.read(Path::new("/home/.vimrc")).unwrap(), b"set number");
+        assert_eq!(filesystem.read(Path::new("/home/vim/vimrc")).unwrap(), b"set number");
+        assert_eq!(filesystem.read_link(Path::new("/home/.vimrc")).unwrap(), PathBuf::from("../dotfiles/vim/vimrc"));
+        assert_eq!(filesystem.canonicalize(Path::new("/home/vim/vimrc")).unwrap(), PathBuf::from("/dotfiles/vim/vimrc"));
+        assert!(filesystem.symlink_metadata(Path::new("/home/vim")).unwrap().is_symlink());
+        assert!(filesystem.metadata(Path::new("/home/vim")).unwrap().is_dir());
+    }
+
+    #[test]
+    fn dangling_symlink_only_exists_as_link() {
+        let filesystem = MemoryFilesystem::new();
+        filesystem.add_symlink("/home/.vimrc", "/dotfiles/vimrc").unwrap();
+        assert!(!filesystem.exists(Path::new("/home/.vimrc")));
+        assert!(filesystem.actually_exists(Path::new("/home/.vimrc")));
+        assert!(filesystem.is_symbolic(Path::new("/home/.vimrc")));
+        assert_eq!(kind(filesystem.read(Path::new("/home/.vimrc"))), ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn symlink_loop_fails() {
+        let filesystem = MemoryFilesystem::new();
+        filesystem.add_symlink("/a", "/b").unwrap();
+        filesystem.add_symlink("/b", "a").unwrap();
+        assert_eq!(kind(filesystem.metadata(Path::new("/a"))), ErrorKind::Other);
+        assert_eq!(kind(filesystem.read(Path::new("/a/file"))), ErrorKind::Other);
+        assert!(filesystem.symlink_metadata(Path::new("/a")).unwrap().is_symlink());
+    }
+
+    #[test]
+    fn write_follows_symlink_and_remove_does_not() {
+        let filesystem = MemoryFilesystem::new();
+        filesystem.add_file("/dotfiles/vimrc", b"old").unwrap();
+        filesystem.add_symlink("/home/.vimrc", "/dotfiles/vimrc").unwrap();
+        filesystem.write(Path::new("/home/.vimrc"), b"new").unwrap();
+        assert_eq!(filesystem.read(Path::new("/dotfiles/vimrc")).unwrap(), b"new");
+        filesystem.remove(Path::new("/home/.vimrc")).unwrap();
+        assert!(!filesystem.actually_exists(Path::new("/home/.vimrc")));
+        assert!(filesystem.is_file(Path::new("/dotfiles/vimrc")));
+    }
+
+    #[test]
+    fn file_in_path_is_not_a_directory() {
+        let filesystem = MemoryFilesystem::new();
+        filesystem.add_file("/home/.vimrc", b"").unwrap();
+        assert_eq!(kind(filesystem.write(Path::new("/home/.vimrc/x"), b"")), ErrorKind::NotADirectory);
+        assert_eq!(kind(filesystem.mkdir(Path::new("/home/.vimrc"))), ErrorKind::AlreadyExists);
+        assert_eq!(kind(filesystem.write(Path::new("/home/missing/x"), b"")), ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn rename_moves_directory_with_its_children() {
+        let filesystem = MemoryFilesystem::new();
+        filesystem.add_file("/dotfiles/vim/vimrc", b"set number").unwrap();
+        filesystem.add_symlink("/dotfiles/vim/colors", "../colors").unwrap();
+        filesystem.rename(Path::new("/dotfiles/vim"), Path::new("/dotfiles/nvim")).unwrap();
+        assert!(!filesystem.actually_exists(Path::new("/dotfiles/vim")));
+        assert_eq!(filesystem.read(Path::new("/dotfiles/nvim/vimrc")).unwrap(), b"set number");
+        assert_eq!(filesystem.read_link(Path::new("/dotfiles/nvim/colors")).unwrap(), PathBuf::from("../colors"));
+    }
+
+    #[test]
+    fn rename_replaces_file_but_not_non_empty_directory() {
+        let filesystem = MemoryFilesystem::new();
+        filesystem.add_file("/a", b"a").unwrap();
+        filesystem.add_file("/b", b"b").unwrap();
+        filesystem.add_file("/full/file", b"").unwrap();
+        filesystem.rename(Path::new("/a"), Path::new("/b")).unwrap();
+        assert_eq!(filesystem.read(Path::new("/b")).unwrap(), b"a");
+        assert_eq!(kind(filesystem.rename(Path::new("/b"), Path::new("/full"))), ErrorKind::DirectoryNotEmpty);
+        assert_eq!(kind(filesystem.rename(Path::new("/full"), Path::new("/full/inner"))), ErrorKind::InvalidInput);
+        assert_eq!(kind(filesystem.rename(Path::new("/missing"), Path::new("/c"))), ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn permissions_are_checked() {
+        let filesystem = MemoryFilesystem::new();
+        filesystem.add_file("/home/secret", b"").unwrap();
+        filesystem.add_file("/home/readonly", b"").unwrap();
+        filesystem.add_file("/etc/hosts", b"").unwrap();
+        filesystem.set_permissions(Path::new("/home/secret"), 0o200).unwrap();
+        filesystem.set_permissions(Path::new("/home/readonly"), 0o444).unwrap();
+        filesystem.set_permissions(Path::new("/etc"), 0o555).unwrap();
+        assert_eq!(kind(filesystem.read(Path::new("/home/secret"))), ErrorKind::PermissionDenied);
+        assert_eq!(kind(filesystem.write(Path::new("/home/readonly"), b"")), ErrorKind::PermissionDenied);
+        assert_eq!(kind(filesystem.write(Path::new("/etc/new"), b"")), ErrorKind::PermissionDenied);
+        assert_eq!(kind(filesystem.remove(Path::new("/etc/hosts"))), ErrorKind::PermissionDenied);
+        assert_eq!(kind(filesystem.rename(Path::new("/home/readonly"), Path::new("/etc/readonly"))), ErrorKind::PermissionDenied);
+        assert_eq!(kind(filesystem.symlink(Path::new("/home"), Path::new("/etc/home"))), ErrorKind::PermissionDenied);
+        assert_eq!(filesystem.metadata(Path::new("/home/readonly")).unwrap().mode, 0o444);
+        // Writing an existing file does not change the entries of its directory.
+        filesystem.write(Path::new("/etc/hosts"), b"127.0.0.1 localhost").unwrap();
+    }
+
+    #[test]
+    fn hard_links_share_content() {
+        let filesystem = MemoryFilesystem::new();
+        filesystem.add_file("/dotfiles/vimrc", b"old").unwrap();
+        filesystem.add_dir("/home").unwrap();
+        filesystem.hard_link(Path::new("/dotfiles/vimrc"), Path::new("/home/.vimrc")).unwrap();
+        let target = filesystem.metadata(Path::new("/dotfiles/vimrc")).unwrap();
+        let link = filesystem.metadata(Path::new("/home/.vimrc")).unwrap();
+        assert_eq!((target.device, target.inode), (link.device, link.inode));
+        filesystem.write(Path::new("/home/.vimrc"), b"new").unwrap();
+        assert_eq!(filesystem.read(Path::new("/dotfiles/vimrc")).unwrap(), b"new");
+        filesystem.remove(Path::new("/dotfiles/vimrc")).unwrap();
+        assert_eq!(filesystem.read(Path::new("/home/.vimrc")).unwrap(), b"new");
+        assert_eq!(kind(filesystem.hard_link(Path::new("/home"), Path::new("/home2"))), ErrorKind::PermissionDenied);
+    }
+
+    #[test]
+    fn failures_are_injected() {
+        let filesystem = MemoryFilesystem::new();
+        filesystem.add_file("/home/.vimrc", b"").unwrap();
+        filesystem.fail("/home/.vimrc", ErrorKind::StorageFull);
+        assert_eq!(kind(filesystem.write(Path::new("/home/.vimrc"), b"")), ErrorKind::StorageFull);
+        assert_eq!(kind(filesystem.read(Path::new("/home/.vimrc"))), ErrorKind::StorageFull);
+    }
+}
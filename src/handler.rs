@@ -0,0 +1,187 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use log::debug;
+
+use crate::error::{Error, Result};
+use crate::filesystem::Filesystem;
+use crate::linker::{Link, LinkStatus, Linker, SYMLINK_MODE};
+use crate::path_extension::PathExtension;
+use crate::state::{State, StateEntry};
+
+/// One change a handler plans, applied by `apply`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Operation {
+    CreateDirectory(PathBuf),
+    /// Remove a file, a symbolic link or a whole directory.
+    Remove(PathBuf),
+    Symlink { path: PathBuf, content: PathBuf },
+    Write { path: PathBuf, content: Vec<u8> },
+    /// Record a state entry for a link path.
+    Record(PathBuf, StateEntry),
+    Forget(PathBuf),
+}
+
+/// The changes that create or delete a link, in order.
+pub type Plan = Vec<Operation>;
+
+/// Deploys the links of one mode. Handlers only plan, so `--simulate` and the state work the same
+/// for every mode.
+pub trait LinkHandler {
+    fn status(&self, link: &Link, state: &State) -> Result<LinkStatus>;
+    fn plan_create(&self, link: &Link, state: &State) -> Result<Plan>;
+    fn plan_delete(&self, link: &Link, state: &State) -> Result<Plan>;
+}
+
+/// Handlers by the mode of the links they deploy.
+pub type Handlers = HashMap<String, Arc<dyn LinkHandler + Send + Sync>>;
+
+/// The handlers of the built-in modes, every `Linker` starts with them.
+pub fn builtin() -> Handlers {
+    let mut handlers: Handlers = HashMap::new();
+    handlers.insert(SYMLINK_MODE.to_string(), Arc::new(SymlinkHandler));
+    return handlers;
+}
+
+/// Apply a plan to `filesystem`. With `simulate` the operations are only logged.
+pub fn apply(filesystem: &dyn Filesystem, plan: &[Operation], state: &mut State, simulate: bool) -> Result<()> {
+    for operation in plan {
+        match operation {
+            Operation::CreateDirectory(path) => {
+                debug!("Create directory: {}.", path.to_str().unwrap());
+                if !simulate {
+                    filesystem.mkdir(path).map_err(|e| Error::new("Failed to create directory.").with_inner_error(&e))?;
+                }
+            }
+            Operation::Remove(path) => {
+                debug!("Delete: {}.", path.to_str().unwrap());
+                if !simulate {
+                    filesystem
+                        .remove(path)
+                        .map_err(|e| Error::new(&format!("Failed to delete `{}`.", path.to_str().unwrap())).with_inner_error(&e))?;
+                }
+            }
+            Operation::Symlink { path, content } => {
+                debug!("Create symbolic link : {} target {}.", path.to_str().unwrap(), content.to_str().unwrap());
+                if !simulate {
+                    filesystem
+                        .symlink(content, path)
+                        .map_err(|e| Error::new("Failed to create symbolic link.").with_inner_error(&e))?;
+                }
+            }
+            Operation::Write { path, content } => {
+                debug!("Write file: {}.", path.to_str().unwrap());
+                if !simulate {
+                    filesystem.write(path, content).map_err(|e| Error::new("Failed to write file.").with_inner_error(&e))?;
+                }
+            }
+            Operation::Record(path, entry) => {
+                if !simulate {
+                    state.set(path, entry.clone());
+                }
+            }
+            Operation::Forget(path) => {
+                if !simulate {
+                    state.remove(path);
+                }
+            }
+        }
+    }
+    return Ok(());
+}
+
+/// The default mode, the link path is a symbolic link to the target.
+pub struct SymlinkHandler;
+
+impl SymlinkHandler {
+    /// Link `path` to `target`, replacing what is at `path` with `force`.
+    pub fn plan_link(filesystem: &dyn Filesystem, path: &Path, target: &Path, relative: bool, force: bool) -> Result<Plan> {
+        let mut plan = vec![];
+        let mut content = target.to_path_buf();
+        if let Some(parent) = path.parent() {
+            if !filesystem.exists(parent) {
+                if filesystem.is_symbolic(parent) && force {
+                    debug!("The parent path is exists, but it's not a directory: {}.", parent.to_str().unwrap());
+                    plan.push(Operation::Remove(parent.to_path_buf()));
+                }
+                plan.push(Operation::CreateDirectory(parent.to_path_buf()));
+            }
+            if relative {
+                content = target.relative_to(canonicalize(filesystem, parent)?).unwrap();
+            }
+        }
+        if filesystem.actually_exists(path) && force {
+            debug!("Force to delete: {}.", path.to_str().unwrap());
+            plan.push(Operation::Remove(path.to_path_buf()));
+        }
+        plan.push(Operation::Symlink {
+            path: path.to_path_buf(),
+            content,
+        });
+        return Ok(plan);
+    }
+
+    /// Remove the symbolic link at `path`, if it points into the workspace.
+    pub fn plan_unlink(filesystem: &dyn Filesystem, workspace: &Path, path: &Path) -> Result<Plan> {
+        let workspace = filesystem
+            .canonicalize(workspace)
+            .map_err(|e| Error::new("Invalid workspace path.").with_inner_error(&e))?;
+        if !filesystem.actually_exists(path) {
+            return Err(Error::new(&format!("`{}` is not exists.", path.to_str().unwrap())));
+        }
+        if !filesystem.is_symbolic(path) {
+            return Err(Error::new(&format!("`{}` is not a symbolic link.", path.to_str().unwrap())));
+        }
+        let mut content = filesystem.read_link(path).map_err(|e| Error::new("Failed to read symbolic link.").with_inner_error(&e))?;
+        if content.is_relative() {
+            content = path
+                .parent()
+                .unwrap()
+                .join(&content)
+                .absolutize()
+                .map_err(|e| Error::new("Invalid symbolic link.").with_inner_error(&e))?;
+        }
+        if content.strip_prefix(&workspace).is_err() {
+            return Err(Error::new(&format!("`{}` is not belong to current workspace.", path.to_str().unwrap())));
+        }
+        return Ok(vec![Operation::Remove(path.to_path_buf())]);
+    }
+}
+
+impl LinkHandler for SymlinkHandler {
+    fn status(&self, link: &Link, _state: &State) -> Result<LinkStatus> {
+        return Ok(Linker::symbolic_link_status(&*link.filesystem, &link.path, &link.target, link.relative));
+    }
+
+    fn plan_create(&self, link: &Link, _state: &State) -> Result<Plan> {
+        return SymlinkHandler::plan_link(&*link.filesystem, &link.path, &link.target, link.relative, link.force);
+    }
+
+    fn plan_delete(&self, link: &Link, _state: &State) -> Result<Plan> {
+        return SymlinkHandler::plan_unlink(&*link.filesystem, &link.workspace, &link.path);
+    }
+}
+
+/// The real path of a directory that may not exist yet: its deepest existing ancestor is
+/// resolved and the rest is appended.
+fn canonicalize(filesystem: &dyn Filesystem, directory: &Path) -> Result<PathBuf> {
+    let mut missing = vec![];
+    let mut existing = directory;
+    while !filesystem.exists(existing) {
+        match (existing.file_name(), existing.parent()) {
+            (Some(name), Some(parent)) => {
+                missing.push(name.to_os_string());
+                existing = parent;
+            }
+            _ => break,
+        }
+    }
+    let mut path = filesystem
+        .canonicalize(existing)
+        .map_err(|e| Error::new("Failed to resolve link directory.").with_inner_error(&e))?;
+    for name in missing.iter().rev() {
+        path.push(name);
+    }
+    return Ok(path);
+}
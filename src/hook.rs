@@ -0,0 +1,167 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use log::debug;
+
+use crate::command;
+use crate::configuration::{HookConfiguration, HooksConfiguration};
+use crate::error::{Error, Result};
+use crate::filesystem::Filesystem;
+use crate::path_extension::PathExtension;
+use crate::report::{Entry, Report};
+use crate::script::Script;
+use crate::variables::Variables;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Event {
+    PreCreate,
+    PostCreate,
+    PreDelete,
+    PostDelete,
+}
+
+impl Event {
+    pub fn name(&self) -> &'static str {
+        return match self {
+            Event::PreCreate => "pre_create",
+            Event::PostCreate => "post_create",
+            Event::PreDelete => "pre_delete",
+            Event::PostDelete => "post_delete",
+        };
+    }
+}
+
+/// What a hook runs, a shell command or a script.
+#[derive(Clone)]
+pub enum Action {
+    Command(String),
+    Script(Script),
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return match self {
+            Action::Command(command) => write!(f, "{}", command),
+            Action::Script(script) => write!(f, "{}", script.name),
+        };
+    }
+}
+
+/// A shell command or script run around creating or deleting links.
+#[derive(Clone)]
+pub struct Hook {
+    pub action: Action,
+    pub directory: PathBuf,
+    pub timeout: Option<Duration>,
+    /// Only run when a link it belongs to is (or, before, will be) changed.
+    pub only_changed: bool,
+    /// The variables of its link or group, commands get them as environment variables.
+    pub variables: Variables,
+    /// What scripts read and write.
+    pub filesystem: Arc<dyn Filesystem>,
+}
+
+/// The hooks of a link or a group.
+#[derive(Clone, Default)]
+pub struct Hooks {
+    pub pre_create: Vec<Hook>,
+    pub post_create: Vec<Hook>,
+    pub pre_delete: Vec<Hook>,
+    pub post_delete: Vec<Hook>,
+}
+
+impl Hooks {
+    pub fn resolve(
+        filesystem: &Arc<dyn Filesystem>,
+        configuration: &Option<HooksConfiguration>,
+        workspace: &Path,
+        variables: &Variables,
+    ) -> Result<Hooks> {
+        let configuration = match configuration {
+            Some(configuration) => configuration,
+            None => return Ok(Hooks::default()),
+        };
+        let resolve = |hooks: &Option<Vec<HookConfiguration>>| -> Result<Vec<Hook>> {
+            let mut result = vec![];
+            for hook in hooks.iter().flatten() {
+                let directory = match hook.cwd {
+                    Some(ref cwd) => Path::new(&variables.expand(cwd)?)
+                        .absolutize_in(workspace)
+                        .map_err(|e| Error::new("Invalid hook directory.").with_inner_error(&e))?,
+                    None => workspace.to_path_buf(),
+                };
+                let action = match (&hook.command, &hook.script) {
+                    (Some(command), None) => Action::Command(command.clone()),
+                    (None, Some(script)) => Action::Script(Script::load(filesystem.as_ref(), workspace, script)?),
+                    _ => return Err(Error::new("A hook needs either a `command` or a `script`.")),
+                };
+                result.push(Hook {
+                    action,
+                    directory,
+                    timeout: hook.timeout.map(Duration::from_secs),
+                    only_changed: hook.only_changed.unwrap_or(false),
+                    variables: variables.clone(),
+                    filesystem: filesystem.clone(),
+                });
+            }
+            return Ok(result);
+        };
+        return Ok(Hooks {
+            pre_create: resolve(&configuration.pre_create)?,
+            post_create: resolve(&configuration.post_create)?,
+            pre_delete: resolve(&configuration.pre_delete)?,
+            post_delete: resolve(&configuration.post_delete)?,
+        });
+    }
+
+    pub fn get(&self, event: Event) -> &[Hook] {
+        return match event {
+            Event::PreCreate => &self.pre_create,
+            Event::PostCreate => &self.post_create,
+            Event::PreDelete => &self.pre_delete,
+            Event::PostDelete => &self.post_delete,
+        };
+    }
+
+    /// Run the hooks of `event` one after another until one fails. With `simulate` they are only
+    /// shown.
+    pub fn run(
+        &self,
+        event: Event,
+        changed: bool,
+        environment: &[(String, String)],
+        simulate: bool,
+        report: &mut Report,
+    ) -> Result<()> {
+        for hook in self.get(event) {
+            if hook.only_changed && !changed {
+                debug!("Skip {} hook, nothing changed: `{}`.", event.name(), hook.action);
+                continue;
+            }
+            report.push(Entry::HookRun {
+                event: event.name().to_string(),
+                command: hook.action.to_string(),
+            });
+            let mut environment = environment.to_vec();
+            environment.push(("IDOT_EVENT".to_string(), event.name().to_string()));
+            environment.push(("IDOT_CHANGED".to_string(), flag(changed)));
+            match hook.action {
+                // Scripts run when simulating too, their actions only log what they would do.
+                Action::Script(ref script) => script.run(&hook.filesystem, &hook.directory, &hook.variables, &environment, simulate)?,
+                Action::Command(_) if simulate => {}
+                Action::Command(ref command) => {
+                    let environment: Vec<(String, String)> = hook.variables.environment().into_iter().chain(environment).collect();
+                    command::run(command, &hook.directory, &environment, hook.timeout)?
+                }
+            }
+        }
+        return Ok(());
+    }
+}
+
+/// `1` or `0`, for environment variables.
+pub fn flag(value: bool) -> String {
+    return if value { "1" } else { "0" }.to_string();
+}
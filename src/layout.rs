@@ -0,0 +1,43 @@
+use std::path::{Path, PathBuf};
+
+use crate::alternate;
+use crate::error::{Error, Result};
+use crate::filesystem::Filesystem;
+use crate::pattern;
+use crate::variables::Variables;
+
+/// Files under `home/` are linked to the same path in `$HOME`, files under `xdg-config/` to the
+/// same path in `$XDG_CONFIG_HOME`.
+pub const MIRROR: &str = "mirror";
+
+/// Workspace directories of the mirror layout and where their files are linked to.
+fn mirror_roots(variables: &Variables) -> Result<Vec<(&'static str, PathBuf)>> {
+    let home = variables
+        .get("HOME")
+        .map(PathBuf::from)
+        .ok_or_else(|| Error::new("`HOME` is not set, it is needed by the mirror layout."))?;
+    let config_home = variables
+        .get("XDG_CONFIG_HOME")
+        .filter(|path| !path.is_empty())
+        .map(PathBuf::from)
+        .unwrap_or_else(|| home.join(".config"));
+    return Ok(vec![("home", home), ("xdg-config", config_home)]);
+}
+
+/// Link path and workspace target of every link the layout implies.
+pub fn implied_links(filesystem: &dyn Filesystem, workspace: &Path, layout: &str, variables: &Variables) -> Result<Vec<(PathBuf, PathBuf)>> {
+    if layout != MIRROR {
+        return Err(Error::new(&format!("Unknown layout `{}`, expected `{}`.", layout, MIRROR)));
+    }
+    let mut result = vec![];
+    for (directory, root) in mirror_roots(variables)? {
+        for file in pattern::files(filesystem, workspace, directory)? {
+            // Alternates are linked once by their base path, the linker selects one.
+            let file = alternate::base_path(file);
+            if !result.iter().any(|(_, existing)| *existing == file) {
+                result.push((root.join(file.strip_prefix(directory).unwrap()), file));
+            }
+        }
+    }
+    return Ok(result);
+}
@@ -0,0 +1,52 @@
+#![allow(clippy::needless_return)]
+//! A dotfiles manager: links, copies, renders and patches the files of a workspace into place as
+//! its configuration says.
+//!
+//! ```no_run
+//! use idot::Linker;
+//!
+//! let linker = Linker::builder("/path/to/dotfiles").simulate(true).build()?;
+//! for entry in linker.create()?.entries {
+//!     println!("{}", entry);
+//! }
+//! # Ok::<(), idot::Error>(())
+//! ```
+//!
+//! Results are returned, diagnostics go to the `log` facade and are only shown if the
+//! application installs a logger.
+
+mod alternate;
+mod command;
+pub mod condition;
+pub mod configuration;
+mod contents;
+mod diff;
+pub mod error;
+pub mod facts;
+pub mod filesystem;
+pub mod handler;
+pub mod hook;
+mod layout;
+pub mod linker;
+pub mod mode;
+pub mod path_extension;
+mod pattern;
+pub mod report;
+mod script;
+pub mod state;
+pub mod task;
+pub mod trust;
+pub mod variables;
+
+pub use crate::configuration::{
+    detect_configuration_path, DefaultGroupConfigurationLoader, GroupConfiguration, GroupConfigurationLoader,
+    JsonGroupConfigurationLoader, LinkConfiguration, TomlGroupConfigurationLoader, YamlGroupConfigurationLoader,
+};
+pub use crate::error::{Error, Result};
+pub use crate::facts::Facts;
+pub use crate::filesystem::{Filesystem, MemoryFilesystem, RealFilesystem};
+pub use crate::handler::{LinkHandler, Operation, Plan};
+pub use crate::linker::{Link, LinkStatus, Linker, LinkerBuilder};
+pub use crate::path_extension::PathExtension;
+pub use crate::report::{Entry, LinkState, Report, Status, TaskState};
+pub use crate::variables::Variables;
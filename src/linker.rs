@@ -1,226 +1,863 @@
-use std::fs;
 use std::io;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::Arc;
 
-use colored::*;
-use log::{debug, error, info};
+use log::debug;
 
+use crate::alternate::{self, Alternate};
+use crate::condition;
 use crate::configuration::*;
+use crate::contents;
 use crate::error::*;
+use crate::facts::Facts;
+use crate::filesystem::{Filesystem, RealFilesystem};
+use crate::handler::{self, Handlers, LinkHandler, SymlinkHandler};
+use crate::hook::{self, Event, Hooks};
+use crate::layout;
+use crate::mode::{block, copy, copy_path, extract, generate, hardlink, merge, patch, remove_path, template};
 use crate::path_extension::PathExtension;
+use crate::pattern;
+use crate::report::{Entry, LinkState, Report, Status, TaskState};
+use crate::state::State;
+use crate::task;
+use crate::trust::{self, CommandChange, TrustedCommand};
+use crate::variables::Variables;
 
-pub struct Linker {}
+/// Deploys the links of one workspace, see `Linker::builder`.
+pub struct Linker {
+    workspace: PathBuf,
+    configuration: GroupConfiguration,
+    variables: Variables,
+    filesystem: Arc<dyn Filesystem>,
+    handlers: Handlers,
+    trust_all: bool,
+    simulate: bool,
+}
+
+/// Options of a `Linker`, the configuration is loaded from the workspace and the facts are
+/// collected unless they are given.
+pub struct LinkerBuilder {
+    workspace: PathBuf,
+    configuration: Option<GroupConfiguration>,
+    facts: Option<Facts>,
+    filesystem: Option<Arc<dyn Filesystem>>,
+    handlers: Handlers,
+    force: bool,
+    trust_all: bool,
+    simulate: bool,
+}
+
+impl LinkerBuilder {
+    pub fn configuration(mut self, configuration: GroupConfiguration) -> Self {
+        self.configuration = Some(configuration);
+        return self;
+    }
+
+    pub fn facts(mut self, facts: Facts) -> Self {
+        self.facts = Some(facts);
+        return self;
+    }
+
+    /// Where links are deployed, the real filesystem by default. Keep a handle of a
+    /// `MemoryFilesystem` to look at it after the linker ran.
+    pub fn filesystem(mut self, filesystem: Arc<dyn Filesystem>) -> Self {
+        self.filesystem = Some(filesystem);
+        return self;
+    }
+
+    /// Overwrite what is in the way of links, like `force` at the top of the configuration.
+    pub fn force(mut self, force: bool) -> Self {
+        self.force = force;
+        return self;
+    }
+
+    /// Run the commands of the configuration without checking that they were allowed, see
+    /// `trust::allow`. Only for workspaces the application trusts itself.
+    pub fn trust_all(mut self, trust_all: bool) -> Self {
+        self.trust_all = trust_all;
+        return self;
+    }
+
+    /// Only report what would be done.
+    pub fn simulate(mut self, simulate: bool) -> Self {
+        self.simulate = simulate;
+        return self;
+    }
+
+    /// Deploy links of `mode` with `handler`, replacing the handler of a built-in mode.
+    pub fn handler<H: LinkHandler + Send + Sync + 'static>(mut self, mode: &str, handler: H) -> Self {
+        self.handlers.insert(mode.to_string(), Arc::new(handler));
+        return self;
+    }
+
+    pub fn build(self) -> Result<Linker> {
+        let workspace = self
+            .workspace
+            .absolutize()
+            .map_err(|e| Error::new("Invalid workspace path").with_inner_error(&e))?;
+        let mut configuration = match self.configuration {
+            Some(configuration) => configuration,
+            None => DefaultGroupConfigurationLoader::new().load(&workspace)?,
+        };
+        if self.force {
+            configuration.force = Some(true);
+        }
+        debug!("configuration: {:?}", configuration);
+        let facts = self.facts.unwrap_or_else(Facts::collect);
+        let filesystem = self.filesystem.unwrap_or_else(|| Arc::new(RealFilesystem));
+        let variables = Variables::new(facts)
+            .with_values(&configuration.variables)?
+            .with_script(&filesystem, &workspace, &configuration.script)?;
+        return Ok(Linker {
+            workspace,
+            configuration,
+            variables,
+            filesystem,
+            handlers: self.handlers,
+            trust_all: self.trust_all,
+            simulate: self.simulate,
+        });
+    }
+}
 
 impl Linker {
-    pub fn create<P: AsRef<Path>>(workspace: P, configuration: &GroupConfiguration, simulate: bool) -> Result<()> {
-        let workspace = workspace.as_ref().absolutize().unwrap();
-        match configuration.links {
-            Some(ref links) => {
-                for (symbolic_link, link_configuration) in links {
-                    let symbolic_link_file_path = Path::new(symbolic_link)
-                        .absolutize()
-                        .map_err(|e| Error::new("Invalid symbol link.").with_inner_error(&e))?;
-                    let target_file_path = workspace
-                        .join(&link_configuration.target)
-                        .absolutize()
-                        .map_err(|e| Error::new("Invalid symbol link.").with_inner_error(&e))?;
-                    let relative = link_configuration
-                        .relative
-                        .unwrap_or(configuration.relative.unwrap_or(false));
-                    let force = link_configuration
-                        .force
-                        .unwrap_or(configuration.force.unwrap_or(false));
-
-                    let result = Linker::create_symbolic_link(
-                        &symbolic_link_file_path,
-                        &target_file_path,
-                        relative,
-                        force,
-                        simulate,
-                    );
-                    if let Err(error) = result {
-                        error!("Failed to create symbolic link: {}. {}", &symbolic_link, &error);
-                    } else {
-                        info!(
-                            "Create symbolic link: `{}` -> `{}`.",
-                            symbolic_link_file_path.to_str().unwrap(),
-                            target_file_path.to_str().unwrap()
-                        );
-                    }
+    pub fn builder<P: AsRef<Path>>(workspace: P) -> LinkerBuilder {
+        return LinkerBuilder {
+            workspace: workspace.as_ref().to_path_buf(),
+            configuration: None,
+            facts: None,
+            filesystem: None,
+            handlers: handler::builtin(),
+            force: false,
+            trust_all: false,
+            simulate: false,
+        };
+    }
+
+    pub fn workspace(&self) -> &Path {
+        return &self.workspace;
+    }
+
+    pub fn configuration(&self) -> &GroupConfiguration {
+        return &self.configuration;
+    }
+
+    /// The variables of the top level of the configuration, with the facts.
+    pub fn variables(&self) -> &Variables {
+        return &self.variables;
+    }
+
+    pub fn filesystem(&self) -> &Arc<dyn Filesystem> {
+        return &self.filesystem;
+    }
+
+    /// Create the links, then run the tasks.
+    pub fn create(&self) -> Result<Report> {
+        let (workspace, simulate) = (&self.workspace, self.simulate);
+        let mut report = Report::new();
+        let untrusted = self.check_trust(&mut report)?;
+        let mut state = State::load(&*self.filesystem, workspace)?;
+        let links = self.links()?;
+        let pending: Vec<bool> = links
+            .iter()
+            .map(|link| link.not_applicable.is_none() && !matches!(self.link_status(link, &state), Ok(LinkStatus::Active)))
+            .collect();
+        let failed = Linker::run_group_hooks(workspace, &links, &pending, Event::PreCreate, &[], simulate, &mut report);
+        let mut changed = vec![false; links.len()];
+        for (index, link) in links.iter().enumerate() {
+            if let Some(ref clause) = link.not_applicable {
+                debug!("Skip not applicable link: `{}`, `{}` is false.", &link.name, clause);
+                continue;
+            }
+            let action = format!("create {}", describe(&link.mode));
+            if link.groups.iter().any(|group| failed.iter().any(|failed| Rc::ptr_eq(group, failed))) {
+                report.fail(action, &link.name, &"A pre_create hook of its group failed.");
+                continue;
+            }
+            let result = link
+                .hooks
+                .run(Event::PreCreate, pending[index], &link.environment(simulate), simulate, &mut report)
+                .and_then(|_| self.create_link(link, &mut state, simulate, &mut report));
+            if let Err(error) = result {
+                report.fail(action, &link.name, &error);
+                continue;
+            }
+            report.push(Entry::Created {
+                mode: link.mode.clone(),
+                path: link.path.clone(),
+                source: link.source(),
+            });
+            changed[index] = pending[index];
+            if let Err(error) = link.hooks.run(Event::PostCreate, changed[index], &link.environment(simulate), simulate, &mut report) {
+                report.fail(format!("run post_create hook of {}", describe(&link.mode)), &link.name, &error);
+            }
+        }
+        Linker::run_group_hooks(workspace, &links, &changed, Event::PostCreate, &failed, simulate, &mut report);
+        let tasks = task::tasks(&self.filesystem, workspace, &self.configuration, &self.variables)?;
+        task::run(&tasks, None, &mut state, &untrusted, simulate, &mut report)?;
+        if !simulate {
+            state.save(&*self.filesystem)?;
+        }
+        return Ok(report);
+    }
+
+    /// Run the tasks, or only the task `name` and the tasks it depends on.
+    pub fn run(&self, name: Option<&str>) -> Result<Report> {
+        let mut report = Report::new();
+        let untrusted = self.check_trust(&mut report)?;
+        let mut state = State::load(&*self.filesystem, &self.workspace)?;
+        let tasks = task::tasks(&self.filesystem, &self.workspace, &self.configuration, &self.variables)?;
+        task::run(&tasks, name, &mut state, &untrusted, self.simulate, &mut report)?;
+        if !self.simulate {
+            state.save(&*self.filesystem)?;
+        }
+        return Ok(report);
+    }
+
+    /// Commands of the configuration that the user didn't allow, none with `trust_all`.
+    fn untrusted(&self) -> Result<Vec<TrustedCommand>> {
+        if self.trust_all {
+            return Ok(vec![]);
+        }
+        return trust::untrusted(&self.filesystem, &self.workspace, &self.configuration, &self.variables);
+    }
+
+    /// Trust the commands of the configuration, see `trust::allow`.
+    pub fn allow(&self) -> Result<Vec<CommandChange>> {
+        return trust::allow(&self.filesystem, &self.workspace, &self.configuration, &self.variables);
+    }
+
+    /// Fail if the configuration has commands the user didn't allow. With `simulate` they are
+    /// only reported, since nothing runs.
+    fn check_trust(&self, report: &mut Report) -> Result<Vec<TrustedCommand>> {
+        let untrusted = self.untrusted()?;
+        if untrusted.is_empty() {
+            return Ok(untrusted);
+        }
+        let lines: Vec<String> = untrusted
+            .iter()
+            .map(|command| format!("Untrusted command in `{}`: `{}`.", command.location, command))
+            .collect();
+        if self.simulate {
+            for line in lines {
+                report.push(Entry::Warning(line));
+            }
+            return Ok(untrusted);
+        }
+        return Err(Error::new(&format!(
+            "{}\n{} command(s) of this workspace are not trusted, review and allow them with `idot allow`.",
+            lines.join("\n"),
+            untrusted.len()
+        )));
+    }
+
+    /// Run the `event` hooks of every group with applicable links, outer groups first for `pre`
+    /// events and last for `post` events, except the `skipped` groups. `changed` tells for each
+    /// link whether it is (or will be) changed. Returns the groups whose hooks failed.
+    fn run_group_hooks(
+        workspace: &Path,
+        links: &[Link],
+        changed: &[bool],
+        event: Event,
+        skipped: &[Rc<Group>],
+        simulate: bool,
+        report: &mut Report,
+    ) -> Vec<Rc<Group>> {
+        let mut groups: Vec<Rc<Group>> = vec![];
+        for link in links.iter().filter(|link| link.not_applicable.is_none()) {
+            for group in &link.groups {
+                if !groups.iter().chain(skipped).any(|known| Rc::ptr_eq(known, group)) {
+                    groups.push(group.clone());
                 }
             }
-            None => {}
+        }
+        if event == Event::PostCreate || event == Event::PostDelete {
+            groups.reverse();
+        }
+        let mut failed = vec![];
+        for group in groups {
+            let group_changed = links
+                .iter()
+                .zip(changed)
+                .any(|(link, changed)| *changed && link.groups.iter().any(|member| Rc::ptr_eq(member, &group)));
+            if let Err(error) = group.hooks.run(event, group_changed, &group.environment(workspace, simulate), simulate, report) {
+                report.fail(format!("run {} hook of group", event.name()), &group.name, &error);
+                failed.push(group);
+            }
+        }
+        return failed;
+    }
+
+    fn links(&self) -> Result<Vec<Link>> {
+        let mut result = vec![];
+        self.collect_links("", &self.configuration, &self.variables, Scope::default(), &mut result)?;
+        return order(result);
+    }
+
+    fn collect_links(&self, name: &str, group: &GroupConfiguration, variables: &Variables, scope: Scope, result: &mut Vec<Link>) -> Result<()> {
+        let (filesystem, workspace) = (&self.filesystem, &self.workspace);
+        let variables = variables.clone().with_values(&group.variables)?.with_script(filesystem, workspace, &group.script)?;
+        let mut scope = scope;
+        scope.relative = group.relative.unwrap_or(scope.relative);
+        scope.force = group.force.unwrap_or(scope.force);
+        if scope.not_applicable.is_none() {
+            scope.not_applicable = condition::failing_clause(filesystem, workspace, &group.when, &variables)?;
+        }
+        scope.groups.push(Rc::new(Group {
+            name: name.to_string(),
+            hooks: Hooks::resolve(filesystem, &group.hooks, workspace, &variables)?,
+        }));
+        let mut links = vec![];
+        for (symbolic_link, link_configuration) in group.links.iter().flatten() {
+            for (symbolic_link, target) in Linker::expand_link(filesystem.as_ref(), workspace, symbolic_link, link_configuration, &variables)? {
+                let name = variables.expand(&symbolic_link)?;
+                let link = self.resolve_link(name, &target, link_configuration, &variables, &scope)?;
+                links.push(Link {
+                    shared: link_configuration.links.is_some(),
+                    ..link
+                });
+            }
+        }
+        if let Some(ref layout) = group.layout {
+            let default = LinkConfiguration::default();
+            for (path, target) in layout::implied_links(filesystem.as_ref(), workspace, layout, &variables)? {
+                let name = path.to_str().unwrap().to_string();
+                let link = self.resolve_link(name, target.to_str().unwrap(), &default, &variables, &scope)?;
+                let overridden = links
+                    .iter()
+                    .any(|explicit: &Link| explicit.path == link.path || explicit.target == workspace.join(&target));
+                if overridden {
+                    debug!("`{}` is overridden by an explicit link.", link.path.to_str().unwrap());
+                    continue;
+                }
+                links.push(Link {
+                    layout: Some(layout.clone()),
+                    ..link
+                });
+            }
+        }
+        result.append(&mut links);
+        if let Some(ref groups) = group.groups {
+            let mut collected = vec![];
+            for (name, group) in groups {
+                let mut links = vec![];
+                self.collect_links(name, group, &variables, scope.clone(), &mut links)?;
+                collected.push(links);
+            }
+            // Every link of a group follows every link of the groups it comes after.
+            let index = |name: &String| -> Result<usize> {
+                return groups
+                    .iter()
+                    .position(|(group, _)| group == name)
+                    .ok_or_else(|| Error::new(&format!("Unknown group `{}` in dependencies.", name)));
+            };
+            let mut edges = vec![];
+            for (later, (_, group)) in groups.iter().enumerate() {
+                for name in group.after.iter().flatten() {
+                    edges.push((later, index(name)?));
+                }
+                for name in group.before.iter().flatten() {
+                    edges.push((index(name)?, later));
+                }
+            }
+            for (later, earlier) in edges {
+                let paths: Vec<PathBuf> = collected[earlier].iter().map(|link| link.path.clone()).collect();
+                for link in collected[later].iter_mut() {
+                    link.after.extend(paths.iter().cloned());
+                }
+            }
+            for mut links in collected {
+                result.append(&mut links);
+            }
         }
         return Ok(());
     }
 
-    fn create_symbolic_link<P: AsRef<Path>>(
+    fn resolve_link(&self, name: String, target: &str, link_configuration: &LinkConfiguration, variables: &Variables, scope: &Scope) -> Result<Link> {
+        let (filesystem, workspace) = (&self.filesystem, &self.workspace);
+        let path = Path::new(&name)
+            .absolutize()
+            .map_err(|e| Error::new("Invalid symbolic link.").with_inner_error(&e))?;
+        let mut target = workspace
+            .join(target)
+            .absolutize()
+            .map_err(|e| Error::new("Invalid symbolic link.").with_inner_error(&e))?;
+        let alternate = alternate::select(filesystem.as_ref(), &target, &variables.facts)?;
+        if let Some(ref alternate) = alternate {
+            target = alternate.path.clone();
+        }
+        let mode = link_configuration.mode.clone().unwrap_or_else(|| SYMLINK_MODE.to_string());
+        if !MODES.contains(&mode.as_str()) && !self.handlers.contains_key(&mode) {
+            return Err(Error::new(&format!("Unknown mode `{}` of `{}`.", mode, name)));
+        }
+        let contents = link_configuration.contents.unwrap_or(false);
+        if contents && mode != SYMLINK_MODE {
+            return Err(Error::new(&format!("Only symbolic links can link contents, `{}` is in {} mode.", name, mode)));
+        }
+        let mut inputs = vec![];
+        for input in link_configuration.inputs.iter().flatten() {
+            inputs.push(
+                workspace
+                    .join(variables.expand(input)?)
+                    .absolutize()
+                    .map_err(|e| Error::new("Invalid input path.").with_inner_error(&e))?,
+            );
+        }
+        let not_applicable = match scope.not_applicable {
+            Some(ref clause) => Some(clause.clone()),
+            None if alternate.is_none() && !filesystem.actually_exists(&target) && alternate::has_alternates(filesystem.as_ref(), &target) => {
+                Some("no alternate matches".to_string())
+            }
+            None => condition::failing_clause(filesystem, workspace, &link_configuration.when, variables)?,
+        };
+        let dependencies = |names: &Option<Vec<String>>| -> Result<Vec<PathBuf>> {
+            let mut paths = vec![];
+            for name in names.iter().flatten() {
+                paths.push(
+                    Path::new(&variables.expand(name)?)
+                        .absolutize()
+                        .map_err(|e| Error::new("Invalid dependency.").with_inner_error(&e))?,
+                );
+            }
+            return Ok(paths);
+        };
+        return Ok(Link {
+            filesystem: filesystem.clone(),
+            workspace: workspace.to_path_buf(),
+            variables: variables.clone(),
+            name,
+            path,
+            target,
+            relative: link_configuration.relative.unwrap_or(scope.relative),
+            force: link_configuration.force.unwrap_or(scope.force),
+            mode,
+            block: link_configuration.block.clone(),
+            command: link_configuration.command.clone(),
+            inputs,
+            contents,
+            depth: link_configuration.depth,
+            not_applicable,
+            alternate,
+            layout: None,
+            shared: false,
+            after: dependencies(&link_configuration.after)?,
+            before: dependencies(&link_configuration.before)?,
+            hooks: Hooks::resolve(filesystem, &link_configuration.hooks, workspace, variables)?,
+            groups: scope.groups.clone(),
+        });
+    }
+
+    /// The link path and target of every link an entry stands for, with globs and patterns
+    /// expanded against the workspace. A target-keyed entry stands for one link per location.
+    fn expand_link(
+        filesystem: &dyn Filesystem,
+        workspace: &Path,
+        symbolic_link: &str,
+        link_configuration: &LinkConfiguration,
+        variables: &Variables,
+    ) -> Result<Vec<(String, String)>> {
+        if let Some(ref locations) = link_configuration.links {
+            let target = if link_configuration.target.is_empty() {
+                symbolic_link.to_string()
+            } else {
+                link_configuration.target.clone()
+            };
+            let target = variables.expand(&target)?;
+            return Ok(locations.iter().map(|location| (location.clone(), target.clone())).collect());
+        }
+        let exclude = link_configuration.exclude.clone().unwrap_or_default();
+        if let Some(ref pattern) = link_configuration.pattern {
+            return pattern::expand_pattern(filesystem, workspace, symbolic_link, pattern, &exclude);
+        }
+        let target = if link_configuration.target.is_empty() {
+            default_target(symbolic_link)
+        } else {
+            variables.expand(&link_configuration.target)?
+        };
+        if pattern::is_glob(&target) {
+            let links = pattern::expand_glob(filesystem, workspace, symbolic_link, &target, &exclude)?;
+            if links.is_empty() {
+                debug!("No workspace path matches `{}`.", &target);
+            }
+            return Ok(links);
+        }
+        return Ok(vec![(symbolic_link.to_string(), target)]);
+    }
+
+    fn link_status(&self, link: &Link, state: &State) -> Result<LinkStatus> {
+        if link.contents {
+            return contents::status(link, state);
+        }
+        if let Some(handler) = self.handlers.get(&link.mode) {
+            return handler.status(link, state);
+        }
+        return match link.mode.as_str() {
+            copy::MODE => copy::status(link, state),
+            template::MODE => template::status(link, state),
+            block::MODE => block::status(link, state),
+            merge::MODE => merge::status(link, state),
+            extract::MODE => extract::status(link, state),
+            generate::MODE => generate::status(link, state),
+            patch::MODE => patch::status(link, state),
+            hardlink::MODE => hardlink::status(link, state),
+            _ => Err(Error::new(&format!("Unknown mode `{}`.", link.mode))),
+        };
+    }
+
+    fn create_link(&self, link: &Link, state: &mut State, simulate: bool, report: &mut Report) -> Result<()> {
+        if link.contents {
+            return contents::create(link, state, simulate);
+        }
+        if let Some(handler) = self.handlers.get(&link.mode) {
+            return handler::apply(&*link.filesystem, &handler.plan_create(link, state)?, state, simulate);
+        }
+        return match link.mode.as_str() {
+            copy::MODE => copy::create(link, state, simulate),
+            template::MODE => template::create(link, state, simulate),
+            block::MODE => block::create(link, state, simulate),
+            merge::MODE => merge::create(link, state, simulate),
+            extract::MODE => extract::create(link, state, simulate, report),
+            generate::MODE => generate::create(link, state, simulate),
+            patch::MODE => patch::create(link, state, simulate, report),
+            hardlink::MODE => hardlink::create(link, state, simulate),
+            _ => Err(Error::new(&format!("Unknown mode `{}`.", link.mode))),
+        };
+    }
+
+    fn delete_link(&self, link: &Link, state: &mut State, simulate: bool, report: &mut Report) -> Result<()> {
+        if link.contents {
+            return contents::delete(link, state, simulate, report);
+        }
+        if let Some(handler) = self.handlers.get(&link.mode) {
+            return handler::apply(&*link.filesystem, &handler.plan_delete(link, state)?, state, simulate);
+        }
+        return match link.mode.as_str() {
+            copy::MODE => copy::delete(link, state, simulate),
+            template::MODE => template::delete(link, state, simulate),
+            block::MODE => block::delete(link, state, simulate),
+            merge::MODE => merge::delete(link, state, simulate, report),
+            extract::MODE => extract::delete(link, state, simulate, report),
+            generate::MODE => generate::delete(link, state, simulate),
+            patch::MODE => patch::delete(link, state, simulate, report),
+            hardlink::MODE => hardlink::delete(link, state, simulate),
+            _ => Err(Error::new(&format!("Unknown mode `{}`.", link.mode))),
+        };
+    }
+
+    pub(crate) fn create_symbolic_link<P: AsRef<Path>>(
+        filesystem: &dyn Filesystem,
         symbolic_link_file_path: P,
         target_file_path: P,
         relative: bool,
         force: bool,
+        state: &mut State,
         simulate: bool,
-    ) -> io::Result<()> {
-        let symbolic_link_file_path = symbolic_link_file_path.as_ref();
-        let target_file_path = target_file_path.as_ref();
-        let mut link_content = target_file_path.clone().to_path_buf();
-
-        if let Some(symbolic_link_parent_path) = symbolic_link_file_path.parent() {
-            if !symbolic_link_parent_path.exists() {
-                if !symbolic_link_parent_path.is_dir(){
-                    debug!("The parent path is exists, but it's not a directory: {}.", symbolic_link_parent_path.to_str().unwrap());
-                    if force{
-                        debug!("Force clean the parent path: {}.", symbolic_link_parent_path.to_str().unwrap());
-                        if !simulate { fs::remove_file(&symbolic_link_parent_path)? }
+    ) -> Result<()> {
+        let plan = SymlinkHandler::plan_link(
+            filesystem,
+            symbolic_link_file_path.as_ref(),
+            target_file_path.as_ref(),
+            relative,
+            force,
+        )?;
+        return handler::apply(filesystem, &plan, state, simulate);
+    }
+
+    /// Delete the links, in reverse order.
+    pub fn delete(&self) -> Result<Report> {
+        let (workspace, simulate) = (&self.workspace, self.simulate);
+        let mut report = Report::new();
+        self.check_trust(&mut report)?;
+        let mut state = State::load(&*self.filesystem, workspace)?;
+        let links = self.links()?;
+        let pending: Vec<bool> = links
+            .iter()
+            .map(|link| link.not_applicable.is_none() && !matches!(self.link_status(link, &state), Ok(LinkStatus::Inactive)))
+            .collect();
+        let failed = Linker::run_group_hooks(workspace, &links, &pending, Event::PreDelete, &[], simulate, &mut report);
+        let mut changed = vec![false; links.len()];
+        for (index, link) in links.iter().enumerate().rev() {
+            if let Some(ref clause) = link.not_applicable {
+                debug!("Skip not applicable link: `{}`, `{}` is false.", &link.name, clause);
+                continue;
+            }
+            let action = format!("delete {}", describe(&link.mode));
+            if link.groups.iter().any(|group| failed.iter().any(|failed| Rc::ptr_eq(group, failed))) {
+                report.fail(action, &link.name, &"A pre_delete hook of its group failed.");
+                continue;
+            }
+            match self.link_status(link, &state) {
+                Ok(LinkStatus::Inactive) => {
+                    debug!("Don't need delete: `{}`.", &link.path.to_str().unwrap());
+                }
+                Ok(_) => {
+                    let result = link
+                        .hooks
+                        .run(Event::PreDelete, true, &link.environment(simulate), simulate, &mut report)
+                        .and_then(|_| self.delete_link(link, &mut state, simulate, &mut report));
+                    if let Err(error) = result {
+                        report.fail(action, &link.name, &error);
+                        continue;
+                    }
+                    report.push(Entry::Deleted {
+                        mode: link.mode.clone(),
+                        path: link.path.clone(),
+                    });
+                    changed[index] = true;
+                    if let Err(error) = link.hooks.run(Event::PostDelete, true, &link.environment(simulate), simulate, &mut report) {
+                        report.fail(format!("run post_delete hook of {}", describe(&link.mode)), &link.name, &error);
                     }
                 }
-                debug!("Create parent directory: {}.", symbolic_link_parent_path.to_str().unwrap());
-                if !simulate { fs::create_dir_all(&symbolic_link_parent_path)? }
-            }
-            if relative {
-                link_content = target_file_path
-                    .relative_to(&symbolic_link_parent_path.canonicalize()?).unwrap();
+                Err(error) => report.fail(action, &link.name, &error),
             }
         }
+        Linker::run_group_hooks(workspace, &links, &changed, Event::PostDelete, &failed, simulate, &mut report);
+        if !simulate {
+            state.save(&*self.filesystem)?;
+        }
+        return Ok(report);
+    }
 
-        if symbolic_link_file_path.actually_exists() && force {
-            if symbolic_link_file_path.is_file() || symbolic_link_file_path.is_symbolic() {
-                debug!("Force to delete file: {}.", symbolic_link_file_path.to_str().unwrap());
-                if !simulate { fs::remove_file(&symbolic_link_file_path)? }
-            } else {
-                debug!("Force to delete directory: {}.", symbolic_link_file_path.to_str().unwrap());
-                if !simulate { fs::remove_dir_all(&symbolic_link_file_path)? }
+    /// Move the file at a link path into the workspace as its target, then link every location of
+    /// that target to it.
+    pub fn adopt(&self, path: &Path, force: bool) -> Result<Report> {
+        let (workspace, simulate) = (&self.workspace, self.simulate);
+        let mut report = Report::new();
+        self.check_trust(&mut report)?;
+        let mut state = State::load(&*self.filesystem, workspace)?;
+        let path = path.absolutize().map_err(|e| Error::new("Invalid path.").with_inner_error(&e))?;
+        let links = self.links()?;
+        let link = links
+            .iter()
+            .find(|link| link.path == path)
+            .ok_or_else(|| Error::new(&format!("`{}` is not a link in the configuration.", path.to_str().unwrap())))?;
+        if link.mode != SYMLINK_MODE || link.contents {
+            return Err(Error::new(&format!("`{}` is in {} mode, only symbolic links can be adopted.", &link.name, link.mode)));
+        }
+        let filesystem = &*self.filesystem;
+        if !filesystem.actually_exists(&path) || filesystem.is_symbolic(&path) {
+            return Err(Error::new(&format!("`{}` is not a file to adopt.", path.to_str().unwrap())));
+        }
+        if filesystem.actually_exists(&link.target) && !force {
+            return Err(Error::new(&format!(
+                "`{}` is already exists, use `--force` to overwrite it.",
+                link.target.to_str().unwrap()
+            )));
+        }
+        report.push(Entry::Adopted {
+            path: path.clone(),
+            target: link.target.clone(),
+        });
+        if !simulate {
+            move_path(filesystem, &path, &link.target).map_err(|e| Error::new("Failed to move file into the workspace.").with_inner_error(&e))?;
+        }
+        for location in links.iter().filter(|location| location.target == link.target) {
+            if let Some(ref clause) = location.not_applicable {
+                debug!("Skip not applicable link: `{}`, `{}` is false.", &location.name, clause);
+                continue;
+            }
+            // The file is only moved into the workspace when not simulating.
+            let result = if simulate { Ok(()) } else { self.create_link(location, &mut state, simulate, &mut report) };
+            match result {
+                Ok(()) => report.push(Entry::Created {
+                    mode: location.mode.clone(),
+                    path: location.path.clone(),
+                    source: location.source(),
+                }),
+                Err(error) => report.fail(format!("create {}", describe(&location.mode)), &location.name, &error),
             }
         }
-        debug!("Create symbolic link : {} target {}.", symbolic_link_file_path.to_str().unwrap(),link_content.to_str().unwrap());
         if !simulate {
-            return std::os::unix::fs::symlink(&link_content, &symbolic_link_file_path);
-        } else {
-            return Ok(());
+            state.save(&*self.filesystem)?;
         }
+        return Ok(report);
     }
 
-    pub fn delete<P: AsRef<Path>>(workspace: P, configuration: &GroupConfiguration, simulate: bool) -> Result<()> {
-        let workspace = workspace.as_ref();
-        match configuration.links {
-            Some(ref links) => {
-                for (symbolic_link, link_configuration) in links {
-                    let symbolic_link_file_path = Path::new(symbolic_link)
-                        .absolutize()
-                        .map_err(|e| Error::new("Invalid symbolic link.").with_inner_error(&e))?;
-                    let target_file_path = workspace
-                        .join(&link_configuration.target)
-                        .absolutize()
-                        .map_err(|e| Error::new("Invalid symbol link.").with_inner_error(&e))?;
-                    let relative = link_configuration
-                        .relative
-                        .unwrap_or(configuration.relative.unwrap_or(false));
-                    match Linker::symbolic_link_status(&symbolic_link_file_path, &target_file_path, relative) {
-                        LinkStatus::Active => {
-                            let result =
-                                Linker::delete_symbolic_link(workspace, &symbolic_link_file_path, simulate);
-                            if let Err(error) = result {
-                                error!("Failed to delete symbolic link: `{}`. {}", &symbolic_link, &error);
-                            } else {
-                                info!("Delete symbolic link: `{}`.", &symbolic_link_file_path.to_str().unwrap());
-                            }
-                        }
-                        _ => {
-                            debug!("Don't need delete: `{}`.", &symbolic_link_file_path.to_str().unwrap());
-                        }
-                    }
-                }
+    /// Rename a target in the workspace and in the configuration file, then move every link to
+    /// it along.
+    pub fn mv(&self, from: &str, to: &str) -> Result<Report> {
+        let (workspace, simulate) = (&self.workspace, self.simulate);
+        let mut report = Report::new();
+        self.check_trust(&mut report)?;
+        let mut state = State::load(&*self.filesystem, workspace)?;
+        let configuration_path = detect_configuration_path(&*self.filesystem, workspace)
+            .ok_or_else(|| Error::new("Not found configuration file"))?;
+        let source = workspace.join(from).absolutize().map_err(|e| Error::new("Invalid path.").with_inner_error(&e))?;
+        let destination = workspace.join(to).absolutize().map_err(|e| Error::new("Invalid path.").with_inner_error(&e))?;
+        let filesystem = &*self.filesystem;
+        if !filesystem.actually_exists(&source) {
+            return Err(Error::new(&format!("`{}` is not exists.", source.to_str().unwrap())));
+        }
+        if filesystem.actually_exists(&destination) {
+            return Err(Error::new(&format!("`{}` is already exists.", destination.to_str().unwrap())));
+        }
+        let mut active = vec![];
+        for link in self.links()? {
+            if link.target != source || link.not_applicable.is_some() {
+                continue;
+            }
+            match self.link_status(&link, &state)? {
+                LinkStatus::Inactive => {}
+                _ => active.push(link),
             }
-            None => {}
         }
-        return Ok(());
+        for link in &active {
+            debug!("Delete {} before moving its target: `{}`.", describe(&link.mode), link.path.to_str().unwrap());
+            self.delete_link(link, &mut state, simulate, &mut report)?;
+        }
+        report.push(Entry::Moved {
+            from: source.clone(),
+            to: destination.clone(),
+        });
+        if !simulate {
+            move_path(filesystem, &source, &destination).map_err(|e| Error::new("Failed to move target.").with_inner_error(&e))?;
+            if rename_target(filesystem, &configuration_path, from, to)? == 0 {
+                report.push(Entry::Warning(format!(
+                    "`{}` is not written in `{}`, update the configuration by hand.",
+                    from,
+                    configuration_path.to_str().unwrap()
+                )));
+            }
+        }
+        for link in active {
+            let link = Link {
+                target: destination.clone(),
+                ..link
+            };
+            // The target is only moved when not simulating.
+            let result = if simulate { Ok(()) } else { self.create_link(&link, &mut state, simulate, &mut report) };
+            match result {
+                Ok(()) => report.push(Entry::Created {
+                    mode: link.mode.clone(),
+                    path: link.path.clone(),
+                    source: link.source(),
+                }),
+                Err(error) => report.fail(format!("create {}", describe(&link.mode)), &link.name, &error),
+            }
+        }
+        if !simulate {
+            state.save(&*self.filesystem)?;
+        }
+        return Ok(report);
     }
 
-    fn delete_symbolic_link<P: AsRef<Path>>(
-        workspace: P,
-        symbolic_link_file_path: P,
-        simulate: bool,
-    ) -> io::Result<()> {
-        let workspace = workspace.as_ref().canonicalize().unwrap();
-        let symbolic_link_file_path = symbolic_link_file_path.as_ref();
-
-        if !symbolic_link_file_path.actually_exists() {
-            return Err(io::Error::new(io::ErrorKind::NotFound, format!("`{}` is not exists.",
-                                                                       symbolic_link_file_path.to_str().unwrap())));
-        }
-        if !symbolic_link_file_path.is_symbolic() {
-            return Err(io::Error::new(io::ErrorKind::NotFound, format!("`{}` is not a symbolic link.",
-                                                                       symbolic_link_file_path.to_str().unwrap())));
+    /// Delete symbolic links into the workspace that no link creates any more, from the `clean`
+    /// targets and the directories of links implied by the layout. Links whose target still
+    /// exists are only deleted with `force`.
+    pub fn clean(&self) -> Result<Report> {
+        let (workspace, simulate, variables, filesystem) = (&self.workspace, self.simulate, &self.variables, &self.filesystem);
+        let mut report = Report::new();
+        let links = self.links()?;
+        let mut directories: Vec<(PathBuf, bool)> = vec![];
+        if let Some(ref clean) = self.configuration.clean {
+            for (directory, target) in clean.targets.iter().flatten() {
+                let directory = Path::new(&variables.expand(directory)?)
+                    .absolutize()
+                    .map_err(|e| Error::new("Invalid clean target.").with_inner_error(&e))?;
+                directories.push((directory, target.force.or(clean.force).unwrap_or(false)));
+            }
         }
-        let mut link_content = fs::read_link(&symbolic_link_file_path).unwrap();
-        if link_content.is_relative() {
-            link_content = symbolic_link_file_path
-                .parent().unwrap()
-                .join(&link_content)
-                .absolutize()?;
+        for link in links.iter().filter(|link| link.layout.is_some()) {
+            let directory = link.path.parent().unwrap().to_path_buf();
+            if !directories.iter().any(|(path, _)| *path == directory) {
+                directories.push((directory, false));
+            }
         }
-        if link_content.strip_prefix(&workspace).is_ok() {
-            if !simulate {
-                return fs::remove_file(symbolic_link_file_path);
-            }else{
-                return Ok(());
+        for (directory, force) in directories {
+            let entries = match filesystem.read_dir(&directory) {
+                Ok(entries) => entries,
+                Err(error) => {
+                    debug!("Skip clean target `{}`. {}", directory.to_str().unwrap(), error);
+                    continue;
+                }
+            };
+            for path in entries {
+                if !filesystem.is_symbolic(&path) || links.iter().any(|link| link.path == path) {
+                    continue;
+                }
+                let mut link_content = filesystem.read_link(&path).map_err(|e| Error::new("Failed to read symbolic link.").with_inner_error(&e))?;
+                if link_content.is_relative() {
+                    link_content = directory.join(&link_content).absolutize().unwrap();
+                }
+                if link_content.strip_prefix(workspace).is_err() {
+                    continue;
+                }
+                if filesystem.exists(&link_content) && !force {
+                    debug!("Keep unknown symbolic link: `{}`, its target exists.", path.to_str().unwrap());
+                    continue;
+                }
+                if !simulate {
+                    if let Err(error) = filesystem.remove(&path) {
+                        report.fail("clean symbolic link".to_string(), path.to_str().unwrap(), &error);
+                        continue;
+                    }
+                }
+                report.push(Entry::Cleaned {
+                    path,
+                    target: link_content,
+                });
             }
-        } else {
-            return Err(io::Error::new(io::ErrorKind::Other, format!("`{}` is not belong to current workspace.",
-                                                                    symbolic_link_file_path.to_str().unwrap())));
         }
+        return Ok(report);
     }
 
-    pub fn status<P: AsRef<Path>>(workspace: P, configuration: &GroupConfiguration) -> Result<()> {
-        let workspace = workspace.as_ref();
-        match configuration.links {
-            Some(ref links) => {
-                for (symbolic_link, link_configuration) in links {
-                    let symbolic_link_file_path = Path::new(symbolic_link)
-                        .absolutize()
-                        .map_err(|e| Error::new("Invalid symbol link.").with_inner_error(&e))?;
-                    let target_file_path = workspace
-                        .join(&link_configuration.target)
-                        .absolutize()
-                        .map_err(|e| Error::new("Invalid symbol link.").with_inner_error(&e))?;
-                    let relative = link_configuration
-                        .relative
-                        .unwrap_or(configuration.relative.unwrap_or(false));
-                    match Linker::symbolic_link_status(
-                        &symbolic_link_file_path,
-                        &target_file_path,
-                        relative,
-                    ) {
-                        LinkStatus::Active => {
-                            info!(
-                                "`{}` -> `{}`.",
-                                symbolic_link_file_path.to_str().unwrap().green(),
-                                target_file_path.to_str().unwrap(),
-                            );
-                        }
-                        LinkStatus::Inactive => {
-                            info!(
-                                "`{}` -> `{}`.",
-                                symbolic_link_file_path.to_str().unwrap().red(),
-                                target_file_path.to_str().unwrap(),
-                            );
-                        }
-                    };
-                }
+    /// The status of every link and task, the guards of untrusted tasks don't run.
+    pub fn status(&self) -> Result<Status> {
+        let workspace = &self.workspace;
+        let state = State::load(&*self.filesystem, workspace)?;
+        let mut links = vec![];
+        for link in self.links()? {
+            if let Some(ref alternate) = link.alternate {
+                debug!("Select alternate `{}` for `{}`, {}.", alternate.path.to_str().unwrap(), &link.name, alternate.reason);
             }
-            None => {}
+            let status = match link.not_applicable {
+                Some(_) => None,
+                None => Some(self.link_status(&link, &state)),
+            };
+            links.push(LinkState {
+                source: link.source(),
+                alternate: link.alternate.map(|alternate| alternate.reason),
+                name: link.name,
+                path: link.path,
+                target: link.target,
+                mode: link.mode,
+                contents: link.contents,
+                shared: link.shared,
+                not_applicable: link.not_applicable,
+                layout: link.layout,
+                status,
+            });
         }
-        return Ok(());
+        let untrusted = self.untrusted()?;
+        let mut tasks = vec![];
+        for task in task::tasks(&self.filesystem, workspace, &self.configuration, &self.variables)? {
+            let trusted = task.trusted(&untrusted);
+            let status = if trusted && task.not_applicable.is_none() {
+                Some(task.status(&state))
+            } else {
+                None
+            };
+            tasks.push(TaskState {
+                name: task.name,
+                command: task.command,
+                not_applicable: task.not_applicable,
+                trusted,
+                status,
+            });
+        }
+        return Ok(Status { links, tasks });
     }
 
-    fn symbolic_link_status<P: AsRef<Path>>(
+    pub(crate) fn symbolic_link_status<P: AsRef<Path>>(
+        filesystem: &dyn Filesystem,
         symbolic_link_file_path: P,
         target_file_path: P,
         relative: bool,
     ) -> LinkStatus {
         let symbolic_link_file_path = symbolic_link_file_path.as_ref();
         let target_file_path = target_file_path.as_ref();
-        if !symbolic_link_file_path.actually_exists() {
+        if !filesystem.actually_exists(symbolic_link_file_path) {
             debug!(
                 "`{}` {} ",
                 symbolic_link_file_path.to_str().unwrap(),
@@ -228,7 +865,7 @@ impl Linker {
             );
             return LinkStatus::Inactive;
         }
-        if !symbolic_link_file_path.is_symbolic() {
+        if !filesystem.is_symbolic(symbolic_link_file_path) {
             debug!(
                 "`{}` {}",
                 symbolic_link_file_path.to_str().unwrap(),
@@ -237,7 +874,7 @@ impl Linker {
             return LinkStatus::Inactive;
         }
 
-        let link_content = symbolic_link_file_path.read_link().unwrap();
+        let link_content = filesystem.read_link(symbolic_link_file_path).unwrap();
         let mut link_content_file_path = link_content.clone();
         if link_content.is_relative() {
             link_content_file_path = symbolic_link_file_path
@@ -274,8 +911,524 @@ impl Linker {
     }
 }
 
+/// Default mode, the link path is a symbolic link to the target.
+pub const SYMLINK_MODE: &str = "symlink";
+
+const MODES: &[&str] = &[
+    SYMLINK_MODE,
+    copy::MODE,
+    hardlink::MODE,
+    template::MODE,
+    block::MODE,
+    merge::MODE,
+    extract::MODE,
+    generate::MODE,
+    patch::MODE,
+];
+
+/// How a link of `mode` is called in messages, e.g. `symbolic link`.
+pub fn describe(mode: &str) -> &'static str {
+    return match mode {
+        copy::MODE => "copy",
+        template::MODE => "rendered file",
+        block::MODE => "block",
+        merge::MODE => "merged document",
+        extract::MODE => "extracted archive",
+        generate::MODE => "generated file",
+        patch::MODE => "patch",
+        hardlink::MODE => "hardlink",
+        SYMLINK_MODE => "symbolic link",
+        _ => "link",
+    };
+}
+
+/// Sort links so that each one comes after its dependencies and after the links that contain its
+/// path, keeping the configuration order otherwise.
+fn order(links: Vec<Link>) -> Result<Vec<Link>> {
+    let count = links.len();
+    let mut earlier: Vec<Vec<usize>> = vec![vec![]; count];
+    let find = |path: &PathBuf, link: &Link| -> Result<Vec<usize>> {
+        let found: Vec<usize> = (0..count).filter(|index| links[*index].path == *path).collect();
+        if found.is_empty() {
+            return Err(Error::new(&format!(
+                "Unknown link `{}` in dependencies of `{}`.",
+                path.to_str().unwrap(),
+                &link.name
+            )));
+        }
+        return Ok(found);
+    };
+    for (index, link) in links.iter().enumerate() {
+        for path in &link.after {
+            earlier[index].extend(find(path, link)?);
+        }
+        for path in &link.before {
+            for later in find(path, link)? {
+                earlier[later].push(index);
+            }
+        }
+        for (other, parent) in links.iter().enumerate() {
+            if link.path != parent.path && link.path.starts_with(&parent.path) {
+                earlier[index].push(other);
+            }
+        }
+    }
+    let mut done = vec![false; count];
+    let mut sorted = vec![];
+    while sorted.len() < count {
+        let next = (0..count).find(|index| !done[*index] && earlier[*index].iter().all(|earlier| done[*earlier]));
+        match next {
+            Some(index) => {
+                done[index] = true;
+                sorted.push(index);
+            }
+            None => {
+                let cycle: Vec<&str> = (0..count).filter(|index| !done[*index]).map(|index| links[index].name.as_str()).collect();
+                return Err(Error::new(&format!("Dependency cycle between `{}`.", cycle.join("`, `"))));
+            }
+        }
+    }
+    let mut links: Vec<Option<Link>> = links.into_iter().map(Some).collect();
+    return Ok(sorted.into_iter().map(|index| links[index].take().unwrap()).collect());
+}
+
+/// Rename `source` to `destination`, copying it when they are on different filesystems.
+fn move_path(filesystem: &dyn Filesystem, source: &Path, destination: &Path) -> io::Result<()> {
+    remove_path(filesystem, destination)?;
+    if let Some(parent) = destination.parent() {
+        filesystem.mkdir(parent)?;
+    }
+    if filesystem.rename(source, destination).is_ok() {
+        return Ok(());
+    }
+    copy_path(filesystem, source, destination)?;
+    return remove_path(filesystem, source);
+}
+
+/// Target of a link without one, the file name of the link path without its leading dot.
+fn default_target(symbolic_link: &str) -> String {
+    let name = Path::new(symbolic_link)
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or_default();
+    return name.strip_prefix('.').unwrap_or(name).to_string();
+}
+
+/// A link entry resolved against the workspace, the variables and the facts.
+pub struct Link {
+    /// Where the link is deployed, see `LinkerBuilder::filesystem`.
+    pub filesystem: Arc<dyn Filesystem>,
+    pub workspace: PathBuf,
+    pub variables: Variables,
+    pub name: String,
+    pub path: PathBuf,
+    pub target: PathBuf,
+    pub relative: bool,
+    pub force: bool,
+    pub mode: String,
+    /// Name of the managed block in `block` mode.
+    pub block: Option<String>,
+    /// Command whose output is the file in `generate` mode.
+    pub command: Option<String>,
+    /// Workspace files the generated file depends on.
+    pub inputs: Vec<PathBuf>,
+    /// Link each child of the target directory instead of the directory itself.
+    pub contents: bool,
+    /// How deep `contents` unfolds the target directory, every file is linked without it.
+    pub depth: Option<usize>,
+    pub not_applicable: Option<String>,
+    pub alternate: Option<Alternate>,
+    /// The layout that implies the link, if it isn't listed in `links`.
+    pub layout: Option<String>,
+    /// The link is one of the locations of a target-keyed entry.
+    pub shared: bool,
+    /// Link paths that must be created before this link.
+    pub after: Vec<PathBuf>,
+    /// Link paths that must be created after this link.
+    pub before: Vec<PathBuf>,
+    pub hooks: Hooks,
+    /// The groups the link is in, outermost first.
+    pub groups: Vec<Rc<Group>>,
+}
+
+impl Link {
+    /// Environment variables of the link's hooks.
+    fn environment(&self, simulate: bool) -> Vec<(String, String)> {
+        return vec![
+            ("IDOT_LINK".to_string(), self.path.to_str().unwrap().to_string()),
+            ("IDOT_TARGET".to_string(), self.target.to_str().unwrap().to_string()),
+            ("IDOT_MODE".to_string(), self.mode.clone()),
+            ("IDOT_WORKSPACE".to_string(), self.workspace.to_str().unwrap().to_string()),
+            ("IDOT_SIMULATE".to_string(), hook::flag(simulate)),
+        ];
+    }
+}
+
+/// A group of links and its hooks. The top-level configuration is the group without a name.
+pub struct Group {
+    pub name: String,
+    pub hooks: Hooks,
+}
+
+impl Group {
+    /// Environment variables of the group's hooks.
+    fn environment(&self, workspace: &Path, simulate: bool) -> Vec<(String, String)> {
+        return vec![
+            ("IDOT_GROUP".to_string(), self.name.clone()),
+            ("IDOT_WORKSPACE".to_string(), workspace.to_str().unwrap().to_string()),
+            ("IDOT_SIMULATE".to_string(), hook::flag(simulate)),
+        ];
+    }
+}
+
+/// What a group passes down to its links and groups.
+#[derive(Clone, Default)]
+struct Scope {
+    relative: bool,
+    force: bool,
+    not_applicable: Option<String>,
+    groups: Vec<Rc<Group>>,
+}
+
+impl Link {
+    /// What the link path is created from, for messages.
+    pub fn source(&self) -> String {
+        return match self.command {
+            Some(ref command) if self.mode == generate::MODE => format!("$({})", command),
+            _ => self.target.to_str().unwrap().to_string(),
+        };
+    }
+}
 
-enum LinkStatus {
+#[derive(Debug, Clone, PartialEq)]
+pub enum LinkStatus {
     Inactive,
     Active,
+    /// The link path is unchanged, but the workspace changed since it was created.
+    Outdated(String),
+    /// The link path has local changes that `create` won't overwrite without `--force`.
+    Modified(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filesystem::MemoryFilesystem;
+
+    fn linker(filesystem: &Arc<MemoryFilesystem>, configuration: &str) -> LinkerBuilder {
+        return Linker::builder("/dotfiles")
+            .configuration(serde_yaml::from_str(configuration).unwrap())
+            .facts(Facts::new())
+            .filesystem(filesystem.clone())
+            .trust_all(true);
+    }
+
+    fn statuses(linker: &Linker) -> Vec<LinkStatus> {
+        return linker.status().unwrap().links.into_iter().map(|link| link.status.unwrap().unwrap()).collect();
+    }
+
+    const VIMRC: &str = "links:\n  /home/me/.vimrc: vimrc\n";
+
+    fn paths(linker: &Linker) -> Vec<PathBuf> {
+        return linker.status().unwrap().links.into_iter().map(|link| link.path).collect();
+    }
+
+    #[test]
+    fn links_come_after_containing_links_and_their_dependencies() {
+        let filesystem = Arc::new(MemoryFilesystem::new());
+        let configuration = "links:
+  /home/me/.config/nvim: nvim
+  /home/me/.local/bin/tool: { target: tool, after: [/home/me/.local/bin] }
+  /home/me/.config: config
+  /home/me/.vimrc: { target: vimrc, before: [/home/me/.config] }
+  /home/me/.local/bin: bin
+";
+        let linker = linker(&filesystem, configuration).build().unwrap();
+        let expected: Vec<PathBuf> =
+            ["/home/me/.vimrc", "/home/me/.config", "/home/me/.config/nvim", "/home/me/.local/bin", "/home/me/.local/bin/tool"]
+                .iter()
+                .map(PathBuf::from)
+                .collect();
+        assert_eq!(paths(&linker), expected);
+    }
+
+    #[test]
+    fn group_links_come_after_the_groups_they_depend_on() {
+        let filesystem = Arc::new(MemoryFilesystem::new());
+        let configuration = "groups:\n  fonts:\n    after: [base]\n    links: { /home/me/.fonts: fonts }\n  base:\n    links: { /home/me/.bin: bin }\n";
+        let linker = linker(&filesystem, configuration).build().unwrap();
+        assert_eq!(paths(&linker), vec![PathBuf::from("/home/me/.bin"), PathBuf::from("/home/me/.fonts")]);
+    }
+
+    #[test]
+    fn dependency_cycle_fails() {
+        let filesystem = Arc::new(MemoryFilesystem::new());
+        let configuration = "links:
+  /home/me/.vimrc: vimrc
+  /home/me/a: { target: a, after: [/home/me/b] }
+  /home/me/b: { target: b, after: [/home/me/a] }
+";
+        let linker = linker(&filesystem, configuration).build().unwrap();
+        let error = linker.status().err().unwrap();
+        assert_eq!(error.to_string(), "Dependency cycle between `/home/me/a`, `/home/me/b`.");
+    }
+
+    #[test]
+    fn unknown_dependency_fails() {
+        let filesystem = Arc::new(MemoryFilesystem::new());
+        let configuration = "links:\n  /home/me/a: { target: a, after: [/home/me/b] }\n";
+        let linker = linker(&filesystem, configuration).build().unwrap();
+        let error = linker.status().err().unwrap();
+        assert_eq!(error.to_string(), "Unknown link `/home/me/b` in dependencies of `/home/me/a`.");
+    }
+
+    #[test]
+    fn create_status_and_delete_symbolic_link() {
+        let filesystem = Arc::new(MemoryFilesystem::new());
+        filesystem.add_file("/dotfiles/vimrc", b"set number").unwrap();
+        let linker = linker(&filesystem, VIMRC).build().unwrap();
+        assert_eq!(statuses(&linker), vec![LinkStatus::Inactive]);
+        assert!(!linker.create().unwrap().has_failures());
+        assert!(filesystem.is_symbolic(Path::new("/home/me/.vimrc")));
+        assert_eq!(filesystem.read(Path::new("/home/me/.vimrc")).unwrap(), b"set number");
+        assert_eq!(statuses(&linker), vec![LinkStatus::Active]);
+        assert!(!linker.delete().unwrap().has_failures());
+        assert!(!filesystem.actually_exists(Path::new("/home/me/.vimrc")));
+        assert!(filesystem.is_file(Path::new("/dotfiles/vimrc")));
+    }
+
+    #[test]
+    fn create_keeps_existing_file_unless_forced() {
+        let filesystem = Arc::new(MemoryFilesystem::new());
+        filesystem.add_file("/dotfiles/vimrc", b"set number").unwrap();
+        filesystem.add_file("/home/me/.vimrc", b"mine").unwrap();
+        assert!(linker(&filesystem, VIMRC).build().unwrap().create().unwrap().has_failures());
+        assert_eq!(filesystem.read(Path::new("/home/me/.vimrc")).unwrap(), b"mine");
+        assert!(!linker(&filesystem, VIMRC).force(true).build().unwrap().create().unwrap().has_failures());
+        assert!(filesystem.is_symbolic(Path::new("/home/me/.vimrc")));
+    }
+
+    #[test]
+    fn create_reports_permission_error() {
+        let filesystem = Arc::new(MemoryFilesystem::new());
+        filesystem.add_file("/dotfiles/vimrc", b"set number").unwrap();
+        filesystem.add_dir("/home/me").unwrap();
+        filesystem.set_permissions(Path::new("/home/me"), 0o555).unwrap();
+        let report = linker(&filesystem, VIMRC).build().unwrap().create().unwrap();
+        assert!(matches!(report.entries[..], [Entry::Failed { ref name, .. }] if name == "/home/me/.vimrc"));
+        assert!(!filesystem.actually_exists(Path::new("/home/me/.vimrc")));
+    }
+
+    #[test]
+    fn simulate_changes_nothing() {
+        let filesystem = Arc::new(MemoryFilesystem::new());
+        filesystem.add_file("/dotfiles/vimrc", b"set number").unwrap();
+        let before = filesystem.paths();
+        let report = linker(&filesystem, VIMRC).simulate(true).build().unwrap().create().unwrap();
+        assert!(matches!(report.entries[..], [Entry::Created { .. }]));
+        assert_eq!(filesystem.paths(), before);
+    }
+
+    #[test]
+    fn copy_is_outdated_after_workspace_change() {
+        let filesystem = Arc::new(MemoryFilesystem::new());
+        filesystem.add_file("/dotfiles/vimrc", b"set number").unwrap();
+        let linker = linker(&filesystem, "links:\n  /home/me/.vimrc:\n    target: vimrc\n    mode: copy\n")
+            .build()
+            .unwrap();
+        assert!(!linker.create().unwrap().has_failures());
+        assert!(filesystem.is_file(Path::new("/home/me/.vimrc")) && !filesystem.is_symbolic(Path::new("/home/me/.vimrc")));
+        assert_eq!(statuses(&linker), vec![LinkStatus::Active]);
+        filesystem.write(Path::new("/dotfiles/vimrc"), b"set nonumber").unwrap();
+        assert!(matches!(statuses(&linker)[..], [LinkStatus::Outdated(_)]));
+        assert!(!linker.create().unwrap().has_failures());
+        assert_eq!(filesystem.read(Path::new("/home/me/.vimrc")).unwrap(), b"set nonumber");
+        assert!(!linker.delete().unwrap().has_failures());
+        assert!(!filesystem.actually_exists(Path::new("/home/me/.vimrc")));
+    }
+
+    const TOOLS: &str = "links:\n  /opt/tools:\n    target: tools.tar\n    mode: extract\n";
+
+    /// A tar archive of `bin/tool` containing `content`.
+    fn tools_archive(content: &[u8]) -> Vec<u8> {
+        let mut archive = tar::Builder::new(vec![]);
+        let mut header = tar::Header::new_gnu();
+        header.set_size(content.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        archive.append_data(&mut header, "bin/tool", content).unwrap();
+        return archive.into_inner().unwrap();
+    }
+
+    /// A workspace with `tools.tar`, an archive of `bin/tool` containing `new`.
+    fn tools() -> Arc<MemoryFilesystem> {
+        let filesystem = Arc::new(MemoryFilesystem::new());
+        filesystem.add_file("/dotfiles/tools.tar", &tools_archive(b"new")).unwrap();
+        return filesystem;
+    }
+
+    #[test]
+    fn extract_delete_keeps_directories_that_were_there() {
+        let filesystem = tools();
+        filesystem.add_dir("/opt/tools/bin").unwrap();
+        let extract = linker(&filesystem, TOOLS).build().unwrap();
+        assert!(!extract.create().unwrap().has_failures());
+        assert!(!extract.create().unwrap().has_failures());
+        assert!(!extract.delete().unwrap().has_failures());
+        assert!(!filesystem.actually_exists(Path::new("/opt/tools/bin/tool")));
+        assert!(filesystem.is_dir(Path::new("/opt/tools/bin")));
+
+        let filesystem = tools();
+        let extract = linker(&filesystem, TOOLS).build().unwrap();
+        assert!(!extract.create().unwrap().has_failures());
+        filesystem.write(Path::new("/dotfiles/tools.tar"), &tools_archive(b"newer")).unwrap();
+        assert!(!extract.create().unwrap().has_failures());
+        assert_eq!(filesystem.read(Path::new("/opt/tools/bin/tool")).unwrap(), b"newer");
+        assert!(!extract.delete().unwrap().has_failures());
+        assert!(!filesystem.actually_exists(Path::new("/opt/tools")));
+    }
+
+    #[test]
+    fn extract_keeps_files_in_the_way_unless_forced() {
+        let filesystem = tools();
+        filesystem.add_file("/opt/tools/bin/tool", b"mine").unwrap();
+        let extract = linker(&filesystem, TOOLS).build().unwrap();
+        assert_eq!(statuses(&extract), vec![LinkStatus::Modified("1 file(s) in the way".to_string())]);
+        assert!(extract.create().unwrap().has_failures());
+        assert_eq!(filesystem.read(Path::new("/opt/tools/bin/tool")).unwrap(), b"mine");
+        filesystem.write(Path::new("/opt/tools/bin/tool"), b"new").unwrap();
+        assert!(!extract.create().unwrap().has_failures());
+        assert_eq!(statuses(&extract), vec![LinkStatus::Active]);
+        filesystem.write(Path::new("/opt/tools/bin/tool"), b"mine").unwrap();
+        assert!(!linker(&filesystem, TOOLS).force(true).build().unwrap().create().unwrap().has_failures());
+        assert_eq!(filesystem.read(Path::new("/opt/tools/bin/tool")).unwrap(), b"new");
+    }
+
+    #[test]
+    fn extract_reports_kept_files_as_warnings() {
+        let filesystem = tools();
+        let extract = linker(&filesystem, TOOLS).build().unwrap();
+        assert!(!extract.create().unwrap().has_failures());
+        filesystem.write(Path::new("/opt/tools/bin/tool"), b"mine").unwrap();
+        let report = extract.delete().unwrap();
+        assert!(report
+            .entries
+            .iter()
+            .any(|entry| *entry == Entry::Warning("Keep `/opt/tools/bin/tool`, it was changed after extract.".to_string())));
+        assert_eq!(filesystem.read(Path::new("/opt/tools/bin/tool")).unwrap(), b"mine");
+    }
+
+    struct NoopHandler;
+
+    impl LinkHandler for NoopHandler {
+        fn status(&self, _: &Link, _: &State) -> Result<LinkStatus> {
+            return Ok(LinkStatus::Active);
+        }
+
+        fn plan_create(&self, _: &Link, _: &State) -> Result<handler::Plan> {
+            return Ok(vec![]);
+        }
+
+        fn plan_delete(&self, _: &Link, _: &State) -> Result<handler::Plan> {
+            return Ok(vec![]);
+        }
+    }
+
+    #[test]
+    fn hook_commands_get_variables_and_home_directory() {
+        let filesystem = Arc::new(MemoryFilesystem::new());
+        filesystem.add_file("/dotfiles/vimrc", b"set number").unwrap();
+        let configuration = "variables:\n  editor: vim\nlinks:\n  /home/me/.vimrc:\n    target: vimrc\n    hooks:\n      post_create:\n        command: test \"$editor\" = vim && test \"$PWD\" = \"$HOME\"\n        cwd: \"~\"\n";
+        let hooks = &linker(&filesystem, configuration).build().unwrap().links().unwrap()[0].hooks;
+        assert_eq!(hooks.post_create[0].directory, PathBuf::from(shellexpand::tilde("~").as_ref()));
+        assert!(!linker(&filesystem, configuration).build().unwrap().create().unwrap().has_failures());
+    }
+
+    const HARDLINK: &str = "links:\n  /home/me/.vimrc:\n    target: vimrc\n    mode: hardlink\n";
+
+    /// Save a file the way editors do, by writing a new file and renaming it over the old one.
+    fn save_by_rename(filesystem: &MemoryFilesystem, path: &str, content: &[u8]) {
+        let temporary = format!("{}.swp", path);
+        filesystem.write(Path::new(&temporary), content).unwrap();
+        filesystem.rename(Path::new(&temporary), Path::new(path)).unwrap();
+    }
+
+    #[test]
+    fn hardlink_saved_by_rename_is_relinked_with_its_content() {
+        let filesystem = Arc::new(MemoryFilesystem::new());
+        filesystem.add_file("/dotfiles/vimrc", b"set number").unwrap();
+        let linker = linker(&filesystem, HARDLINK).build().unwrap();
+        assert!(!linker.create().unwrap().has_failures());
+        save_by_rename(&filesystem, "/home/me/.vimrc", b"set nonumber");
+        assert!(matches!(statuses(&linker)[0], LinkStatus::Outdated(_)));
+        assert!(linker.delete().unwrap().has_failures());
+        assert!(!linker.create().unwrap().has_failures());
+        assert_eq!(statuses(&linker), vec![LinkStatus::Active]);
+        assert_eq!(filesystem.read(Path::new("/dotfiles/vimrc")).unwrap(), b"set nonumber");
+    }
+
+    #[test]
+    fn hardlink_with_both_sides_changed_is_kept_unless_forced() {
+        let filesystem = Arc::new(MemoryFilesystem::new());
+        filesystem.add_file("/dotfiles/vimrc", b"set number").unwrap();
+        assert!(!linker(&filesystem, HARDLINK).build().unwrap().create().unwrap().has_failures());
+        save_by_rename(&filesystem, "/home/me/.vimrc", b"set nonumber");
+        save_by_rename(&filesystem, "/dotfiles/vimrc", b"set relativenumber");
+        let linker = linker(&filesystem, HARDLINK).build().unwrap();
+        assert!(matches!(statuses(&linker)[0], LinkStatus::Modified(_)));
+        assert!(linker.create().unwrap().has_failures());
+        assert_eq!(filesystem.read(Path::new("/home/me/.vimrc")).unwrap(), b"set nonumber");
+    }
+
+    const PATCH: &str = "links:\n  /etc/app.conf:\n    target: app.patch\n    mode: patch\n";
+
+    #[test]
+    fn patch_of_missing_file_is_inactive() {
+        let filesystem = Arc::new(MemoryFilesystem::new());
+        filesystem.add_file("/dotfiles/app.patch", b"@@ -1 +1 @@\n-debug = false\n+debug = true\n").unwrap();
+        filesystem.add_file("/etc/app.conf", b"debug = false\n").unwrap();
+        let linker = linker(&filesystem, PATCH).build().unwrap();
+        assert!(!linker.create().unwrap().has_failures());
+        assert_eq!(statuses(&linker), vec![LinkStatus::Active]);
+        filesystem.remove(Path::new("/etc/app.conf")).unwrap();
+        assert_eq!(statuses(&linker), vec![LinkStatus::Inactive]);
+        assert!(!linker.delete().unwrap().has_failures());
+    }
+
+    #[test]
+    fn block_without_end_marker_is_left_alone() {
+        let filesystem = Arc::new(MemoryFilesystem::new());
+        filesystem.add_file("/dotfiles/zshrc", b"export EDITOR=vim\n").unwrap();
+        filesystem.add_file("/home/me/.zshrc", b"# >>> idot:zshrc >>>\nexport EDITOR=vim\n").unwrap();
+        let linker = linker(&filesystem, "links:\n  /home/me/.zshrc:\n    target: zshrc\n    mode: block\n").force(true).build().unwrap();
+        assert!(linker.status().unwrap().links[0].status.as_ref().unwrap().is_err());
+        assert!(linker.create().unwrap().has_failures());
+        assert!(linker.delete().unwrap().has_failures());
+        assert_eq!(filesystem.read(Path::new("/home/me/.zshrc")).unwrap(), b"# >>> idot:zshrc >>>\nexport EDITOR=vim\n");
+    }
+
+    #[test]
+    fn mv_renames_target_in_the_configuration_file() {
+        let filesystem = Arc::new(MemoryFilesystem::new());
+        filesystem.add_file("/dotfiles/vimrc", b"set number").unwrap();
+        filesystem.add_file("/dotfiles/idot.yaml", VIMRC.as_bytes()).unwrap();
+        let linker = linker(&filesystem, VIMRC).build().unwrap();
+        assert!(!linker.create().unwrap().has_failures());
+        assert!(!linker.mv("vimrc", "vim/vimrc").unwrap().has_failures());
+        assert_eq!(filesystem.read_to_string(Path::new("/dotfiles/idot.yaml")).unwrap(), "links:\n  /home/me/.vimrc: vim/vimrc\n");
+        assert_eq!(filesystem.canonicalize(Path::new("/home/me/.vimrc")).unwrap(), PathBuf::from("/dotfiles/vim/vimrc"));
+    }
+
+    #[test]
+    fn handlers_belong_to_their_linker() {
+        let filesystem = Arc::new(MemoryFilesystem::new());
+        filesystem.add_file("/dotfiles/vimrc", b"set number").unwrap();
+        let noop = "links:\n  /home/me/.vimrc:\n    target: vimrc\n    mode: noop\n";
+        let with_handler = linker(&filesystem, noop).handler("noop", NoopHandler).build().unwrap();
+        assert_eq!(statuses(&with_handler), vec![LinkStatus::Active]);
+        assert!(linker(&filesystem, noop).build().unwrap().status().is_err());
+        let replaced = linker(&filesystem, VIMRC).handler(SYMLINK_MODE, NoopHandler).build().unwrap();
+        assert!(!replaced.create().unwrap().has_failures());
+        assert!(!filesystem.actually_exists(Path::new("/home/me/.vimrc")));
+    }
 }
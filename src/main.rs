@@ -1,20 +1,19 @@
+#![allow(clippy::needless_return)]
+
 use std::path::PathBuf;
 
 use clap::ArgMatches;
-use log::{self, debug, error};
+use idot::mode::template;
+use idot::{DefaultGroupConfigurationLoader, Error, Facts, GroupConfigurationLoader, Linker, PathExtension, RealFilesystem, Result};
+use log::{self, debug, error, info};
 
 use crate::application::get_matches;
-use crate::configuration::*;
-use crate::error::*;
-use crate::linker::Linker;
-use crate::path_extension::PathExtension;
+use crate::output::{print_changes, print_report, print_status};
 
 mod application;
-mod configuration;
-mod error;
-mod linker;
 mod logger;
-mod path_extension;
+mod output;
+mod plugin;
 
 fn main() {
     let matches = get_matches();
@@ -39,31 +38,93 @@ fn handler(matches: ArgMatches<'static>) -> Result<()> {
         .absolutize()
         .map_err(|e| Error::new("Invalid workspace path").with_inner_error(&e))?;
     let simulate = matches.occurrences_of("simulate") > 0;
+    let mut facts = Facts::collect();
+    for assignment in matches.values_of("fact").into_iter().flatten() {
+        facts = facts.with_override(assignment)?;
+    }
+    debug!("facts: {:?}", facts);
+    let linker = |force: bool| Linker::builder(&workspace).facts(facts.clone()).force(force).simulate(simulate).build();
     let loader = DefaultGroupConfigurationLoader::new();
     match matches.subcommand() {
         ("status", Some(_matches)) => {
-            let configuration = loader.load(&workspace)?;
-            debug!("configuration: {:?}", configuration);
-            Linker::status(&workspace, &configuration)
+            print_status(&linker(false)?.status()?);
+            Ok(())
         }
         ("create", Some(matches)) => {
+            print_report(&linker(matches.occurrences_of("force") > 0)?.create()?);
+            Ok(())
+        }
+        ("delete", Some(_matches)) => {
+            print_report(&linker(false)?.delete()?);
+            Ok(())
+        }
+        ("clean", Some(_matches)) => {
+            print_report(&linker(false)?.clean()?);
+            Ok(())
+        }
+        ("adopt", Some(matches)) => {
+            let path = PathBuf::from(matches.value_of("path").unwrap());
             let force = matches.occurrences_of("force") > 0;
-            let mut configuration = loader.load(&workspace)?;
-            if force {
-                configuration.force = Some(force);
+            print_report(&linker(false)?.adopt(&path, force)?);
+            Ok(())
+        }
+        ("mv", Some(matches)) => {
+            let from = matches.value_of("from").unwrap();
+            let to = matches.value_of("to").unwrap();
+            print_report(&linker(false)?.mv(from, to)?);
+            Ok(())
+        }
+        ("run", Some(matches)) => {
+            print_report(&linker(false)?.run(matches.value_of("task"))?);
+            Ok(())
+        }
+        ("allow", Some(_matches)) => {
+            let changes = linker(false)?.allow()?;
+            if changes.is_empty() {
+                info!("All commands of this workspace are trusted.");
+            } else {
+                print_changes(&changes);
+                info!("Allow the commands of `{}`.", workspace.to_str().unwrap());
             }
-            debug!("configuration: {:?}", configuration);
-            Linker::create(&workspace, &configuration, simulate)
+            Ok(())
         }
-        ("delete", Some(_matches)) => {
-            let configuration = loader.load(&workspace)?;
-            debug!("configuration: {:?}", configuration);
-            Linker::delete(&workspace, &configuration, simulate)
+        ("render", Some(matches)) => {
+            let linker = linker(false)?;
+            let target = workspace
+                .join(matches.value_of("target").unwrap())
+                .absolutize()
+                .map_err(|e| Error::new("Invalid target path").with_inner_error(&e))?;
+            info!("{}", template::render(linker.filesystem(), &workspace, &target, linker.variables())?);
+            Ok(())
+        }
+        ("facts", Some(matches)) => show_facts(&facts, matches.value_of("format").unwrap_or("text")),
+        (name, Some(matches)) => {
+            let arguments: Vec<&str> = matches.values_of("").into_iter().flatten().collect();
+            let configuration = match idot::detect_configuration_path(&RealFilesystem, &workspace) {
+                Some(path) => Some((path, loader.load(&workspace)?)),
+                None => None,
+            };
+            plugin::run(name, &arguments, &workspace, configuration, simulate)
         }
         _ => {
-            let configuration = loader.load(&workspace)?;
-            debug!("configuration: {:?}", configuration);
-            Linker::status(&workspace, &configuration)
+            print_status(&linker(false)?.status()?);
+            Ok(())
+        }
+    }
+}
+
+fn show_facts(facts: &Facts, format: &str) -> Result<()> {
+    match format {
+        "json" => {
+            let content = serde_json::to_string_pretty(facts)
+                .map_err(|e| Error::new("Failed to serialize facts.").with_inner_error(&e))?;
+            info!("{}", content);
+        }
+        _ => {
+            for (key, value) in facts.iter() {
+                info!("{}: {}", key, value);
+            }
         }
     }
+    return Ok(());
 }
@@ -0,0 +1,193 @@
+
+use log::debug;
+
+use crate::error::{Error, Result};
+use crate::linker::{Link, LinkStatus};
+use crate::mode::managed_status;
+use crate::state::{hash_bytes, State, StateEntry};
+
+pub const MODE: &str = "block";
+
+/// Name of the block, given by `block` or derived from the target path in the workspace.
+fn block_name(link: &Link) -> String {
+    if let Some(ref name) = link.block {
+        return name.clone();
+    }
+    return link
+        .target
+        .strip_prefix(&link.workspace)
+        .unwrap_or(&link.target)
+        .to_str()
+        .unwrap()
+        .to_string();
+}
+
+fn state_key(link: &Link) -> String {
+    return format!("{}#{}", link.path.to_str().unwrap(), block_name(link));
+}
+
+fn markers(name: &str) -> (String, String) {
+    return (format!("# >>> idot:{} >>>", name), format!("# <<< idot:{} <<<", name));
+}
+
+/// Line range of the block including its markers. A begin marker without its end marker is an
+/// error, rather than no block, so the rest of the file is never taken for the block or duplicated.
+fn find_block(lines: &[&str], name: &str) -> Result<Option<(usize, usize)>> {
+    let (begin, end) = markers(name);
+    let start = match lines.iter().position(|line| line.trim_end() == begin) {
+        Some(start) => start,
+        None => return Ok(None),
+    };
+    return match lines[start..].iter().position(|line| line.trim_end() == end) {
+        Some(length) => Ok(Some((start, start + length))),
+        None => Err(Error::new(&format!("Block `{}` has no end marker `{}`.", name, end))),
+    };
+}
+
+fn read_snippet(link: &Link) -> Result<String> {
+    let mut snippet = link.filesystem.read_to_string(&link.target)
+        .map_err(|e| Error::new("Failed to read snippet.").with_inner_error(&e))?;
+    if !snippet.is_empty() && !snippet.ends_with('\n') {
+        snippet.push('\n');
+    }
+    return Ok(snippet);
+}
+
+/// Content of the block currently in the destination file, without its markers.
+fn read_block(link: &Link) -> Result<Option<String>> {
+    if !link.filesystem.is_file(&link.path) {
+        return Ok(None);
+    }
+    let content = link.filesystem.read_to_string(&link.path)
+        .map_err(|e| Error::new("Failed to read file.").with_inner_error(&e))?;
+    let lines: Vec<&str> = content.lines().collect();
+    return Ok(find_block(&lines, &block_name(link))?.map(|(start, end)| {
+        lines[start + 1..end]
+            .iter()
+            .map(|line| format!("{}\n", line))
+            .collect::<String>()
+    }));
+}
+
+pub fn status(link: &Link, state: &State) -> Result<LinkStatus> {
+    let block = match read_block(link)? {
+        Some(block) => block,
+        None => return Ok(LinkStatus::Inactive),
+    };
+    let snippet = read_snippet(link)?;
+    return Ok(managed_status(
+        Some(hash_bytes(snippet.as_bytes())),
+        hash_bytes(block.as_bytes()),
+        state.get(state_key(link)),
+        MODE,
+    ));
+}
+
+pub fn create(link: &Link, state: &mut State, simulate: bool) -> Result<()> {
+    let snippet = read_snippet(link)?;
+    match status(link, state)? {
+        LinkStatus::Active => {
+            debug!("Block is current: `{}`.", link.path.to_str().unwrap());
+        }
+        LinkStatus::Modified(detail) if !link.force => {
+            return Err(Error::new(&format!(
+                "Block in `{}` has local changes ({}), use `--force` to overwrite it.",
+                link.path.to_str().unwrap(),
+                detail
+            )));
+        }
+        _ => {
+            let name = block_name(link);
+            let (begin, end) = markers(&name);
+            let content = if link.filesystem.is_file(&link.path) {
+                link.filesystem.read_to_string(&link.path).map_err(|e| Error::new("Failed to read file.").with_inner_error(&e))?
+            } else {
+                String::new()
+            };
+            let lines: Vec<&str> = content.lines().collect();
+            let block = format!("{}\n{}{}\n", begin, snippet, end);
+            let updated = match find_block(&lines, &name)? {
+                Some((start, finish)) => {
+                    let mut updated = String::new();
+                    for line in &lines[..start] {
+                        updated.push_str(line);
+                        updated.push('\n');
+                    }
+                    updated.push_str(&block);
+                    for line in &lines[finish + 1..] {
+                        updated.push_str(line);
+                        updated.push('\n');
+                    }
+                    updated
+                }
+                None if content.is_empty() => block,
+                None if content.ends_with('\n') => format!("{}{}", content, block),
+                None => format!("{}\n{}", content, block),
+            };
+            debug!("Write block `{}` to `{}`.", name, link.path.to_str().unwrap());
+            if !simulate {
+                if let Some(parent) = link.path.parent() {
+                    link.filesystem.mkdir(parent)
+                        .map_err(|e| Error::new("Failed to create parent directory.").with_inner_error(&e))?;
+                }
+                link.filesystem.write(&link.path, updated.as_bytes()).map_err(|e| Error::new("Failed to write file.").with_inner_error(&e))?;
+            }
+        }
+    }
+    if !simulate {
+        state.set(
+            state_key(link),
+            StateEntry {
+                mode: MODE.to_string(),
+                target: link.target.to_str().unwrap().to_string(),
+                hash: Some(hash_bytes(snippet.as_bytes())),
+                ..Default::default()
+            },
+        );
+    }
+    return Ok(());
+}
+
+pub fn delete(link: &Link, state: &mut State, simulate: bool) -> Result<()> {
+    if let LinkStatus::Modified(detail) = status(link, state)? {
+        return Err(Error::new(&format!(
+            "Block in `{}` has local changes ({}).",
+            link.path.to_str().unwrap(),
+            detail
+        )));
+    }
+    let content = link.filesystem.read_to_string(&link.path).map_err(|e| Error::new("Failed to read file.").with_inner_error(&e))?;
+    let lines: Vec<&str> = content.lines().collect();
+    let (start, end) = find_block(&lines, &block_name(link))?
+        .ok_or_else(|| Error::new(&format!("Block is not exists in `{}`.", link.path.to_str().unwrap())))?;
+    let mut updated = String::new();
+    for line in lines[..start].iter().chain(lines[end + 1..].iter()) {
+        updated.push_str(line);
+        updated.push('\n');
+    }
+    if !simulate {
+        link.filesystem.write(&link.path, updated.as_bytes()).map_err(|e| Error::new("Failed to write file.").with_inner_error(&e))?;
+        state.remove(state_key(link));
+    }
+    return Ok(());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn find_block_between_markers() {
+        let lines = vec!["export A=1", "# >>> idot:zsh >>>", "export B=2", "# <<< idot:zsh <<<", "export C=3"];
+        assert_eq!(find_block(&lines, "zsh").unwrap(), Some((1, 3)));
+        assert_eq!(find_block(&lines, "bash").unwrap(), None);
+    }
+
+    #[test]
+    fn find_block_fails_without_end_marker() {
+        let lines = vec!["export A=1", "# >>> idot:zsh >>>", "export B=2"];
+        assert!(find_block(&lines, "zsh").is_err());
+        let lines = vec!["# <<< idot:zsh <<<", "# >>> idot:zsh >>>"];
+        assert!(find_block(&lines, "zsh").is_err());
+    }
+}
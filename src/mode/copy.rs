@@ -0,0 +1,76 @@
+use log::debug;
+
+use crate::error::{Error, Result};
+use crate::linker::{Link, LinkStatus};
+use crate::mode::{copy_path, hash_path, managed_status, remove_path};
+use crate::state::{State, StateEntry};
+
+pub const MODE: &str = "copy";
+
+pub fn status(link: &Link, state: &State) -> Result<LinkStatus> {
+    let workspace_hash = hash_path(&*link.filesystem, &link.target)
+        .map_err(|e| Error::new("Failed to read target.").with_inner_error(&e))?;
+    let destination_hash = match hash_path(&*link.filesystem, &link.path)
+        .map_err(|e| Error::new("Failed to read copy.").with_inner_error(&e))?
+    {
+        Some(hash) => hash,
+        None => return Ok(LinkStatus::Inactive),
+    };
+    return Ok(managed_status(workspace_hash, destination_hash, state.get(&link.path), MODE));
+}
+
+pub fn create(link: &Link, state: &mut State, simulate: bool) -> Result<()> {
+    match status(link, state)? {
+        LinkStatus::Active => {
+            debug!("Copy is in sync: `{}`.", link.path.to_str().unwrap());
+        }
+        LinkStatus::Modified(detail) if !link.force => {
+            return Err(Error::new(&format!(
+                "`{}` has local changes ({}), use `--force` to overwrite it.",
+                link.path.to_str().unwrap(),
+                detail
+            )));
+        }
+        _ => {
+            debug!("Copy `{}` to `{}`.", link.target.to_str().unwrap(), link.path.to_str().unwrap());
+            if !simulate {
+                copy_path(&*link.filesystem, &link.target, &link.path)
+                    .map_err(|e| Error::new("Failed to copy target.").with_inner_error(&e))?;
+            }
+        }
+    }
+    if !simulate {
+        let hash = hash_path(&*link.filesystem, &link.path).map_err(|e| Error::new("Failed to read copy.").with_inner_error(&e))?;
+        state.set(
+            &link.path,
+            StateEntry {
+                mode: MODE.to_string(),
+                target: link.target.to_str().unwrap().to_string(),
+                hash,
+                ..Default::default()
+            },
+        );
+    }
+    return Ok(());
+}
+
+pub fn delete(link: &Link, state: &mut State, simulate: bool) -> Result<()> {
+    match status(link, state)? {
+        LinkStatus::Inactive => {
+            return Err(Error::new(&format!("`{}` is not exists.", link.path.to_str().unwrap())));
+        }
+        LinkStatus::Modified(detail) => {
+            return Err(Error::new(&format!(
+                "`{}` has local changes ({}).",
+                link.path.to_str().unwrap(),
+                detail
+            )));
+        }
+        _ => {}
+    }
+    if !simulate {
+        remove_path(&*link.filesystem, &link.path).map_err(|e| Error::new("Failed to delete copy.").with_inner_error(&e))?;
+        state.remove(&link.path);
+    }
+    return Ok(());
+}
@@ -0,0 +1,437 @@
+use std::io::{self, Cursor, Read};
+use std::path::{Component, Path, PathBuf};
+
+use flate2::read::GzDecoder;
+use log::debug;
+
+use crate::error::{Error, Result};
+use crate::filesystem::Filesystem;
+use crate::linker::{Link, LinkStatus};
+use crate::mode::hash_path;
+use crate::report::{Entry, Report};
+use crate::state::{ManifestEntry, State, StateEntry};
+
+pub const MODE: &str = "extract";
+
+enum Format {
+    Tar,
+    TarGzip,
+    TarZstd,
+    Zip,
+}
+
+/// A file, directory or symbolic link of an archive, with its path relative to the destination.
+struct ArchiveEntry {
+    path: PathBuf,
+    kind: EntryKind,
+    mode: Option<u32>,
+}
+
+enum EntryKind {
+    Directory,
+    File(Vec<u8>),
+    Symlink(PathBuf),
+}
+
+struct Archive {
+    entries: Vec<ArchiveEntry>,
+    /// Why entries were left out.
+    skipped: Vec<String>,
+}
+
+fn detect_format(path: &Path) -> Result<Format> {
+    let name = path.file_name().and_then(|name| name.to_str()).unwrap_or("");
+    return if name.ends_with(".tar") {
+        Ok(Format::Tar)
+    } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+        Ok(Format::TarGzip)
+    } else if name.ends_with(".tar.zst") || name.ends_with(".tzst") {
+        Ok(Format::TarZstd)
+    } else if name.ends_with(".zip") {
+        Ok(Format::Zip)
+    } else {
+        Err(Error::new(&format!(
+            "Unknown archive format of `{}`, expected .tar, .tar.gz, .tar.zst or .zip.",
+            path.to_str().unwrap()
+        )))
+    };
+}
+
+/// The entries of an archive, in archive order. Entries that would land outside of the
+/// destination are skipped.
+fn read_archive(filesystem: &dyn Filesystem, archive: &Path) -> io::Result<Archive> {
+    let content = Cursor::new(filesystem.read(archive)?);
+    return match detect_format(archive).map_err(|e| io::Error::other(e.to_string()))? {
+        Format::Tar => read_tar(content),
+        Format::TarGzip => read_tar(GzDecoder::new(content)),
+        Format::TarZstd => read_tar(zstd::Decoder::new(content)?),
+        Format::Zip => read_zip(content),
+    };
+}
+
+/// `path` without `.` components, or `None` if it is absolute, leaves its directory or is empty.
+fn enclosed(path: &Path) -> Option<PathBuf> {
+    let mut result = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(name) => result.push(name),
+            Component::CurDir => {}
+            _ => return None,
+        }
+    }
+    return if result.as_os_str().is_empty() { None } else { Some(result) };
+}
+
+fn read_tar<R: Read>(reader: R) -> io::Result<Archive> {
+    let mut archive = tar::Archive::new(reader);
+    let mut entries = vec![];
+    let mut skipped = vec![];
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let name = entry.path()?.to_path_buf();
+        let path = match enclosed(&name) {
+            Some(path) => path,
+            None if name.components().all(|component| component == Component::CurDir) => continue,
+            None => {
+                skipped.push(format!("Skip unsafe archive entry: `{}`.", name.to_str().unwrap()));
+                continue;
+            }
+        };
+        let kind = match entry.header().entry_type() {
+            tar::EntryType::Directory => EntryKind::Directory,
+            tar::EntryType::Regular | tar::EntryType::Continuous => {
+                let mut content = vec![];
+                entry.read_to_end(&mut content)?;
+                EntryKind::File(content)
+            }
+            tar::EntryType::Symlink => match entry.link_name()? {
+                Some(content) => EntryKind::Symlink(content.to_path_buf()),
+                None => continue,
+            },
+            _ => {
+                skipped.push(format!(
+                    "Skip archive entry `{}`, only files, directories and symbolic links are extracted.",
+                    name.to_str().unwrap()
+                ));
+                continue;
+            }
+        };
+        entries.push(ArchiveEntry {
+            path,
+            kind,
+            mode: entry.header().mode().ok(),
+        });
+    }
+    return Ok(Archive { entries, skipped });
+}
+
+fn read_zip(content: Cursor<Vec<u8>>) -> io::Result<Archive> {
+    let mut archive = zip::ZipArchive::new(content)?;
+    let mut entries = vec![];
+    let mut skipped = vec![];
+    for index in 0..archive.len() {
+        let mut entry = archive.by_index(index)?;
+        let path = match entry.enclosed_name().as_deref().and_then(enclosed) {
+            Some(path) => path,
+            None => {
+                skipped.push(format!("Skip unsafe archive entry: `{}`.", entry.name()));
+                continue;
+            }
+        };
+        let kind = if entry.is_dir() {
+            EntryKind::Directory
+        } else {
+            let mut content = vec![];
+            entry.read_to_end(&mut content)?;
+            if entry.is_symlink() {
+                EntryKind::Symlink(PathBuf::from(String::from_utf8_lossy(&content).to_string()))
+            } else {
+                EntryKind::File(content)
+            }
+        };
+        entries.push(ArchiveEntry {
+            path,
+            kind,
+            mode: entry.unix_mode(),
+        });
+    }
+    return Ok(Archive { entries, skipped });
+}
+
+/// Write the entries into `destination` and return the paths of the written ones and of the
+/// directories that didn't exist before. Entries below a symbolic link of the archive are skipped,
+/// they would be written outside of `destination`.
+fn write_entries(
+    filesystem: &dyn Filesystem,
+    destination: &Path,
+    entries: &[ArchiveEntry],
+    report: &mut Report,
+) -> io::Result<(Vec<PathBuf>, Vec<PathBuf>)> {
+    let mut written = vec![];
+    let mut created = vec![];
+    let mut directories = vec![];
+    for entry in entries {
+        let output = destination.join(&entry.path);
+        let through_symlink = entry
+            .path
+            .ancestors()
+            .skip(1)
+            .filter(|ancestor| !ancestor.as_os_str().is_empty())
+            .any(|ancestor| filesystem.is_symbolic(&destination.join(ancestor)));
+        if through_symlink {
+            report.push(Entry::Warning(format!("Skip unsafe archive entry: `{}`.", entry.path.to_str().unwrap())));
+            continue;
+        }
+        let directory = match entry.kind {
+            EntryKind::Directory => Some(entry.path.as_path()),
+            _ => entry.path.parent(),
+        };
+        if let Some(directory) = directory.filter(|directory| !directory.as_os_str().is_empty()) {
+            created.extend(missing_directories(filesystem, destination, directory));
+            filesystem.mkdir(&destination.join(directory))?;
+        }
+        match entry.kind {
+            EntryKind::Directory => {
+                if let Some(mode) = entry.mode {
+                    directories.push((output, mode));
+                }
+            }
+            EntryKind::File(ref content) => {
+                if filesystem.is_symbolic(&output) {
+                    filesystem.remove(&output)?;
+                }
+                filesystem.write(&output, content)?;
+                if let Some(mode) = entry.mode {
+                    filesystem.set_permissions(&output, mode & 0o7777)?;
+                }
+            }
+            EntryKind::Symlink(ref content) => {
+                if filesystem.actually_exists(&output) {
+                    filesystem.remove(&output)?;
+                }
+                filesystem.symlink(content, &output)?;
+            }
+        }
+        written.push(entry.path.clone());
+    }
+    // Last, a directory without write permission can't get entries anymore.
+    for (directory, mode) in directories.into_iter().rev() {
+        filesystem.set_permissions(&directory, mode & 0o7777)?;
+    }
+    return Ok((written, created));
+}
+
+/// `directory` of `destination` and its ancestors below `destination` that don't exist, outermost
+/// first.
+fn missing_directories(filesystem: &dyn Filesystem, destination: &Path, directory: &Path) -> Vec<PathBuf> {
+    let mut missing: Vec<PathBuf> = directory
+        .ancestors()
+        .filter(|ancestor| !ancestor.as_os_str().is_empty())
+        .take_while(|ancestor| !filesystem.actually_exists(&destination.join(ancestor)))
+        .map(Path::to_path_buf)
+        .collect();
+    missing.reverse();
+    return missing;
+}
+
+/// Every extracted file with its content hash, and the extracted directories that are in `owned`,
+/// the ones extract created. Directories that were there before are left out, so they are never
+/// removed.
+fn manifest(filesystem: &dyn Filesystem, destination: &Path, extracted: &[PathBuf], owned: &[PathBuf]) -> io::Result<Vec<ManifestEntry>> {
+    let mut manifest: Vec<ManifestEntry> = vec![];
+    for path in extracted {
+        let mut ancestors: Vec<&Path> = path.ancestors().skip(1).filter(|path| !path.as_os_str().is_empty()).collect();
+        ancestors.reverse();
+        for directory in ancestors.into_iter().chain(std::iter::once(path.as_path())) {
+            let name = directory.to_str().unwrap().trim_end_matches('/').to_string();
+            if manifest.iter().any(|entry| entry.path == name) {
+                continue;
+            }
+            let full_path = destination.join(directory);
+            let hash = if filesystem.is_dir(&full_path) && !filesystem.is_symbolic(&full_path) {
+                if !owned.iter().any(|owned| owned == directory) {
+                    continue;
+                }
+                None
+            } else {
+                hash_path(filesystem, &full_path)?
+            };
+            manifest.push(ManifestEntry { path: name, hash });
+        }
+    }
+    return Ok(manifest);
+}
+
+fn recorded(link: &Link, state: &State) -> Option<StateEntry> {
+    return match state.get(&link.path) {
+        Some(entry) if entry.mode == MODE => Some(entry.clone()),
+        _ => None,
+    };
+}
+
+/// How many entries would overwrite something at the destination that idot didn't extract:
+/// different files or symbolic links, or anything else than a directory where the archive has
+/// one. Paths of `extracted` are left out.
+fn conflicts(filesystem: &dyn Filesystem, destination: &Path, entries: &[ArchiveEntry], extracted: &[ManifestEntry]) -> usize {
+    return entries
+        .iter()
+        .filter(|entry| !extracted.iter().any(|extracted| Path::new(&extracted.path) == entry.path))
+        .filter(|entry| {
+            let path = destination.join(&entry.path);
+            let metadata = match filesystem.symlink_metadata(&path) {
+                Ok(metadata) => metadata,
+                Err(_) => return false,
+            };
+            return match entry.kind {
+                EntryKind::Directory => !metadata.is_dir(),
+                EntryKind::File(ref content) => !metadata.is_file() || filesystem.read(&path).ok().as_ref() != Some(content),
+                EntryKind::Symlink(ref content) => filesystem.read_link(&path).ok().as_ref() != Some(content),
+            };
+        })
+        .count();
+}
+
+fn conflict_status(link: &Link, extracted: &[ManifestEntry]) -> Result<Option<LinkStatus>> {
+    let filesystem = link.filesystem.as_ref();
+    let archive = read_archive(filesystem, &link.target).map_err(|e| Error::new("Failed to read archive.").with_inner_error(&e))?;
+    return Ok(match conflicts(filesystem, &link.path, &archive.entries, extracted) {
+        0 => None,
+        count => Some(LinkStatus::Modified(format!("{} file(s) in the way", count))),
+    });
+}
+
+pub fn status(link: &Link, state: &State) -> Result<LinkStatus> {
+    let entry = match recorded(link, state) {
+        Some(entry) => entry,
+        None => return Ok(conflict_status(link, &[])?.unwrap_or(LinkStatus::Inactive)),
+    };
+    let manifest = entry.manifest.clone().unwrap_or_default();
+    let mut missing = 0;
+    let mut modified = 0;
+    for file in &manifest {
+        let path = link.path.join(&file.path);
+        if !link.filesystem.actually_exists(&path) {
+            missing += 1;
+        } else if file.hash.is_some() && hash_path(link.filesystem.as_ref(), &path).map_err(|e| Error::new("Failed to read file.").with_inner_error(&e))? != file.hash {
+            modified += 1;
+        }
+    }
+    if modified > 0 {
+        return Ok(LinkStatus::Modified(format!("{} file(s) modified", modified)));
+    }
+    if missing > 0 {
+        return Ok(LinkStatus::Outdated(format!("{} file(s) missing", missing)));
+    }
+    let archive_hash = hash_path(link.filesystem.as_ref(), &link.target).map_err(|e| Error::new("Failed to read archive.").with_inner_error(&e))?;
+    if archive_hash != entry.hash {
+        // New entries of the archive may be in the way.
+        return Ok(conflict_status(link, &manifest)?.unwrap_or_else(|| LinkStatus::Outdated("workspace-changed".to_string())));
+    }
+    return Ok(LinkStatus::Active);
+}
+
+pub fn create(link: &Link, state: &mut State, simulate: bool, report: &mut Report) -> Result<()> {
+    detect_format(&link.target)?;
+    match status(link, state)? {
+        LinkStatus::Active => {
+            debug!("Archive is extracted: `{}`.", link.path.to_str().unwrap());
+            return Ok(());
+        }
+        LinkStatus::Modified(detail) if !link.force => {
+            return Err(Error::new(&format!(
+                "`{}` has local changes ({}), use `--force` to overwrite it.",
+                link.path.to_str().unwrap(),
+                detail
+            )));
+        }
+        _ => {}
+    }
+    debug!("Extract `{}` to `{}`.", link.target.to_str().unwrap(), link.path.to_str().unwrap());
+    if simulate {
+        return Ok(());
+    }
+    let filesystem = link.filesystem.as_ref();
+    let archive = read_archive(filesystem, &link.target).map_err(|e| Error::new("Failed to read archive.").with_inner_error(&e))?;
+    for skipped in archive.skipped {
+        report.push(Entry::Warning(skipped));
+    }
+    let created_root = !filesystem.exists(&link.path);
+    filesystem
+        .mkdir(&link.path)
+        .map_err(|e| Error::new("Failed to create directory.").with_inner_error(&e))?;
+    let (extracted, mut owned) = write_entries(filesystem, &link.path, &archive.entries, report)
+        .map_err(|e| Error::new("Failed to extract archive.").with_inner_error(&e))?;
+    let previous = recorded(link, state).and_then(|entry| entry.manifest).unwrap_or_default();
+    // Directories created by an earlier extract exist now, they are still extract's.
+    owned.extend(previous.iter().filter(|entry| entry.hash.is_none()).map(|entry| PathBuf::from(&entry.path)));
+    let mut manifest = manifest(filesystem, &link.path, &extracted, &owned)
+        .map_err(|e| Error::new("Failed to read extracted files.").with_inner_error(&e))?;
+    remove_entries(
+        filesystem,
+        &link.path,
+        previous
+            .iter()
+            .filter(|entry| !manifest.iter().any(|current| current.path == entry.path))
+            .cloned()
+            .collect(),
+        report,
+    );
+    if created_root || previous.iter().any(|entry| entry.path.is_empty()) {
+        manifest.insert(
+            0,
+            ManifestEntry {
+                path: String::new(),
+                hash: None,
+            },
+        );
+    }
+    let hash = hash_path(filesystem, &link.target).map_err(|e| Error::new("Failed to read archive.").with_inner_error(&e))?;
+    state.set(
+        &link.path,
+        StateEntry {
+            mode: MODE.to_string(),
+            target: link.target.to_str().unwrap().to_string(),
+            hash,
+            manifest: Some(manifest),
+            ..Default::default()
+        },
+    );
+    return Ok(());
+}
+
+/// Remove extracted files that are unchanged, then the directories that became empty.
+fn remove_entries(filesystem: &dyn Filesystem, destination: &Path, entries: Vec<ManifestEntry>, report: &mut Report) {
+    for entry in entries.iter().rev() {
+        let path = destination.join(&entry.path);
+        if !filesystem.actually_exists(&path) {
+            continue;
+        }
+        let result = match entry.hash {
+            Some(_) => match hash_path(filesystem, &path) {
+                Ok(ref hash) if *hash == entry.hash => filesystem.remove(&path),
+                Ok(_) => {
+                    report.push(Entry::Warning(format!("Keep `{}`, it was changed after extract.", path.to_str().unwrap())));
+                    Ok(())
+                }
+                Err(error) => Err(error),
+            },
+            None => match filesystem.read_dir(&path).map(|entries| entries.is_empty()) {
+                Ok(true) => filesystem.remove(&path),
+                _ => Ok(()),
+            },
+        };
+        if let Err(error) = result {
+            report.push(Entry::Warning(format!("Failed to delete `{}`. {}", path.to_str().unwrap(), error)));
+        }
+    }
+}
+
+pub fn delete(link: &Link, state: &mut State, simulate: bool, report: &mut Report) -> Result<()> {
+    let entry = recorded(link, state)
+        .ok_or_else(|| Error::new(&format!("`{}` was not extracted by idot.", link.path.to_str().unwrap())))?;
+    if !simulate {
+        remove_entries(link.filesystem.as_ref(), &link.path, entry.manifest.unwrap_or_default(), report);
+        state.remove(&link.path);
+    }
+    return Ok(());
+}
@@ -0,0 +1,107 @@
+
+use log::debug;
+
+use crate::command;
+use crate::error::{Error, Result};
+use crate::linker::{Link, LinkStatus};
+use crate::mode::{hash_path, remove_path};
+use crate::state::{hash_bytes, State, StateEntry};
+
+pub const MODE: &str = "generate";
+
+fn command(link: &Link) -> Result<&str> {
+    return link
+        .command
+        .as_deref()
+        .ok_or_else(|| Error::new(&format!("`{}` needs a `command` to generate it.", &link.name)));
+}
+
+/// Hash of everything the output depends on: the command, the variables it references and the
+/// content of the input files.
+fn cache_key(link: &Link) -> Result<String> {
+    let command = command(link)?;
+    let mut key = format!("{}\0", command);
+    for name in link.variables.references(command) {
+        key.push_str(&format!("{}={}\0", name, link.variables.get(&name).unwrap_or_default()));
+    }
+    for input in &link.inputs {
+        let hash = hash_path(&*link.filesystem, input).map_err(|e| Error::new("Failed to read input.").with_inner_error(&e))?;
+        key.push_str(&format!("{}\0{}\0", input.to_str().unwrap(), hash.unwrap_or_default()));
+    }
+    return Ok(hash_bytes(key.as_bytes()));
+}
+
+pub fn status(link: &Link, state: &State) -> Result<LinkStatus> {
+    let destination_hash = match hash_path(&*link.filesystem, &link.path)
+        .map_err(|e| Error::new("Failed to read generated file.").with_inner_error(&e))?
+    {
+        Some(hash) => hash,
+        None => return Ok(LinkStatus::Inactive),
+    };
+    let entry = match state.get(&link.path) {
+        Some(entry) if entry.mode == MODE => entry,
+        _ => return Ok(LinkStatus::Modified("not-generated".to_string())),
+    };
+    if entry.hash.as_ref() != Some(&destination_hash) {
+        return Ok(LinkStatus::Modified("destination-modified".to_string()));
+    }
+    if entry.cache_key.as_ref() != Some(&cache_key(link)?) {
+        return Ok(LinkStatus::Outdated("stale".to_string()));
+    }
+    return Ok(LinkStatus::Active);
+}
+
+pub fn create(link: &Link, state: &mut State, simulate: bool) -> Result<()> {
+    match status(link, state)? {
+        LinkStatus::Active => {
+            debug!("Generated file is up to date: `{}`.", link.path.to_str().unwrap());
+            return Ok(());
+        }
+        LinkStatus::Modified(detail) if !link.force => {
+            return Err(Error::new(&format!(
+                "`{}` has local changes ({}), use `--force` to overwrite it.",
+                link.path.to_str().unwrap(),
+                detail
+            )));
+        }
+        _ => {}
+    }
+    // The command is run as written, the shell expands the variables from its environment.
+    let command = command(link)?;
+    debug!("Generate `{}` by `{}`.", link.path.to_str().unwrap(), command);
+    if simulate {
+        return Ok(());
+    }
+    let output = command::output(command, &link.workspace, &link.variables.environment())?;
+    remove_path(&*link.filesystem, &link.path).map_err(|e| Error::new("Failed to delete file.").with_inner_error(&e))?;
+    if let Some(parent) = link.path.parent() {
+        link.filesystem.mkdir(parent).map_err(|e| Error::new("Failed to create parent directory.").with_inner_error(&e))?;
+    }
+    link.filesystem.write(&link.path, &output).map_err(|e| Error::new("Failed to write generated file.").with_inner_error(&e))?;
+    state.set(
+        &link.path,
+        StateEntry {
+            mode: MODE.to_string(),
+            target: command.to_string(),
+            hash: Some(hash_bytes(&output)),
+            cache_key: Some(cache_key(link)?),
+            ..Default::default()
+        },
+    );
+    return Ok(());
+}
+
+pub fn delete(link: &Link, state: &mut State, simulate: bool) -> Result<()> {
+    if let LinkStatus::Modified(detail) = status(link, state)? {
+        return Err(Error::new(&format!(
+            "`{}` has local changes ({}).",
+            link.path.to_str().unwrap(),
+            detail
+        )));
+    }
+    if !simulate {
+        link.filesystem.remove(&link.path).map_err(|e| Error::new("Failed to delete generated file.").with_inner_error(&e))?;
+        state.remove(&link.path);
+    }
+    return Ok(());
+}
@@ -0,0 +1,167 @@
+use std::path::Path;
+
+use log::debug;
+
+use crate::error::{Error, Result};
+use crate::filesystem::Filesystem;
+use crate::linker::{Link, LinkStatus};
+use crate::mode::{hash_path, remove_path};
+use crate::state::{State, StateEntry};
+
+pub const MODE: &str = "hardlink";
+
+/// The destination was saved as a new file, like editors do, while the target is as it was linked.
+const DESTINATION_SAVED: &str = "broken-hardlink, destination-modified";
+
+pub fn status(link: &Link, state: &State) -> Result<LinkStatus> {
+    let filesystem = link.filesystem.as_ref();
+    if !filesystem.actually_exists(&link.path) {
+        return Ok(LinkStatus::Inactive);
+    }
+    let target = filesystem
+        .metadata(&link.target).map_err(|e| Error::new("Failed to read target.").with_inner_error(&e))?;
+    let destination = filesystem
+        .symlink_metadata(&link.path)
+        .map_err(|e| Error::new("Failed to read hardlink.").with_inner_error(&e))?;
+    if target.device == destination.device && target.inode == destination.inode {
+        return Ok(LinkStatus::Active);
+    }
+    debug!(
+        "`{}` is inode {}:{}, but `{}` is inode {}:{}.",
+        link.path.to_str().unwrap(),
+        destination.device,
+        destination.inode,
+        link.target.to_str().unwrap(),
+        target.device,
+        target.inode
+    );
+    let destination_hash = hash_path(filesystem, &link.path).map_err(|e| Error::new("Failed to read hardlink.").with_inner_error(&e))?;
+    let target_hash = hash_path(filesystem, &link.target).map_err(|e| Error::new("Failed to read target.").with_inner_error(&e))?;
+    let recorded_hash = match state.get(&link.path) {
+        Some(entry) if entry.mode == MODE => Some(entry.hash.clone()),
+        _ => None,
+    };
+    return Ok(match recorded_hash {
+        _ if destination_hash == target_hash => LinkStatus::Outdated("broken-hardlink".to_string()),
+        Some(ref hash) if *hash == destination_hash => LinkStatus::Outdated("broken-hardlink".to_string()),
+        Some(ref hash) if *hash == target_hash => LinkStatus::Outdated(DESTINATION_SAVED.to_string()),
+        Some(_) => LinkStatus::Modified("broken-hardlink, both-modified".to_string()),
+        None => LinkStatus::Modified("not-a-hardlink".to_string()),
+    });
+}
+
+pub fn create(link: &Link, state: &mut State, simulate: bool) -> Result<()> {
+    let filesystem = link.filesystem.as_ref();
+    if !filesystem.is_file(&link.target) {
+        return Err(Error::new(&format!(
+            "`{}` is not a file, only files can be hardlinked.",
+            link.target.to_str().unwrap()
+        )));
+    }
+    match status(link, state)? {
+        LinkStatus::Active => {
+            debug!("Hardlink is active: `{}`.", link.path.to_str().unwrap());
+        }
+        // idot linked it and only the destination changed, so its content goes to the target.
+        LinkStatus::Outdated(ref detail) if detail == DESTINATION_SAVED => {
+            debug!("Relink hardlink: {} target {}.", link.path.to_str().unwrap(), link.target.to_str().unwrap());
+            if !simulate {
+                filesystem
+                    .rename(&link.path, &link.target)
+                    .map_err(|e| Error::new("Failed to move hardlink content to target.").with_inner_error(&e))?;
+                filesystem
+                    .hard_link(&link.target, &link.path)
+                    .map_err(|e| Error::new("Failed to create hardlink.").with_inner_error(&e))?;
+            }
+        }
+        LinkStatus::Modified(detail) if !link.force => {
+            return Err(Error::new(&format!(
+                "`{}` has local changes ({}), use `--force` to overwrite it.",
+                link.path.to_str().unwrap(),
+                detail
+            )));
+        }
+        _ => {
+            check_device(filesystem, &link.target, &link.path)?;
+            debug!("Create hardlink: {} target {}.", link.path.to_str().unwrap(), link.target.to_str().unwrap());
+            if !simulate {
+                remove_path(filesystem, &link.path).map_err(|e| Error::new("Failed to delete file.").with_inner_error(&e))?;
+                if let Some(parent) = link.path.parent() {
+                    filesystem
+                        .mkdir(parent)
+                        .map_err(|e| Error::new("Failed to create parent directory.").with_inner_error(&e))?;
+                }
+                filesystem
+                    .hard_link(&link.target, &link.path)
+                    .map_err(|e| Error::new("Failed to create hardlink.").with_inner_error(&e))?;
+            }
+        }
+    }
+    if !simulate {
+        let hash = hash_path(filesystem, &link.path).map_err(|e| Error::new("Failed to read hardlink.").with_inner_error(&e))?;
+        state.set(
+            &link.path,
+            StateEntry {
+                mode: MODE.to_string(),
+                target: link.target.to_str().unwrap().to_string(),
+                hash,
+                ..Default::default()
+            },
+        );
+    }
+    return Ok(());
+}
+
+pub fn delete(link: &Link, state: &mut State, simulate: bool) -> Result<()> {
+    match status(link, state)? {
+        LinkStatus::Inactive => {
+            return Err(Error::new(&format!("`{}` is not exists.", link.path.to_str().unwrap())));
+        }
+        LinkStatus::Modified(detail) => {
+            return Err(Error::new(&format!(
+                "`{}` has local changes ({}).",
+                link.path.to_str().unwrap(),
+                detail
+            )));
+        }
+        LinkStatus::Outdated(ref detail) if detail == DESTINATION_SAVED => {
+            return Err(Error::new(&format!(
+                "`{}` has local changes ({}), run `create` to keep them in the target first.",
+                link.path.to_str().unwrap(),
+                detail
+            )));
+        }
+        _ => {}
+    }
+    if !simulate {
+        link.filesystem
+            .remove(&link.path)
+            .map_err(|e| Error::new("Failed to delete hardlink.").with_inner_error(&e))?;
+        state.remove(&link.path);
+    }
+    return Ok(());
+}
+
+/// Hardlinks can't cross filesystems, so compare the device of the target with the nearest
+/// existing ancestor of the link path.
+fn check_device(filesystem: &dyn Filesystem, target: &Path, path: &Path) -> Result<()> {
+    let target_device = filesystem
+        .metadata(target)
+        .map_err(|e| Error::new("Failed to read target.").with_inner_error(&e))?
+        .device;
+    let mut ancestor = path.parent();
+    while let Some(directory) = ancestor {
+        if let Ok(metadata) = filesystem.metadata(directory) {
+            if metadata.device != target_device {
+                return Err(Error::new(&format!(
+                    "`{}` and `{}` are on different filesystems, use `\"mode\": \"copy\"` instead.",
+                    path.to_str().unwrap(),
+                    target.to_str().unwrap()
+                )));
+            }
+            break;
+        }
+        ancestor = directory.parent();
+    }
+    return Ok(());
+}
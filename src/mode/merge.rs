@@ -0,0 +1,320 @@
+use std::path::Path;
+
+use log::debug;
+use serde_json::{Map, Value};
+
+use crate::error::{Error, Result};
+use crate::filesystem::Filesystem;
+use crate::linker::{Link, LinkStatus};
+use crate::report::{Entry, Report};
+use crate::state::{hash_bytes, MergedKey, State, StateEntry};
+
+pub const MODE: &str = "merge";
+
+/// Parse a JSON, YAML or TOML document by the extension of its path.
+fn parse(path: &Path, content: &str) -> Result<Value> {
+    if content.trim().is_empty() {
+        return Ok(Value::Object(Map::new()));
+    }
+    let error = |e: &dyn std::fmt::Display| {
+        Error::new(&format!("Failed to parse `{}`.", path.to_str().unwrap())).with_inner_error(e)
+    };
+    return match path.extension().and_then(|extension| extension.to_str()) {
+        Some("json") => serde_json::from_str(content).map_err(|e| error(&e)),
+        Some("yaml") | Some("yml") => serde_yaml::from_str(content).map_err(|e| error(&e)),
+        Some("toml") => toml::from_str(content).map_err(|e| error(&e)),
+        _ => Err(Error::new(&format!(
+            "Unknown document format of `{}`, expected json, yaml or toml.",
+            path.to_str().unwrap()
+        ))),
+    };
+}
+
+fn serialize(path: &Path, document: &Value) -> Result<String> {
+    let error = |e: &dyn std::fmt::Display| {
+        Error::new(&format!("Failed to serialize `{}`.", path.to_str().unwrap())).with_inner_error(e)
+    };
+    return match path.extension().and_then(|extension| extension.to_str()) {
+        Some("json") => serde_json::to_string_pretty(document)
+            .map(|content| content + "\n")
+            .map_err(|e| error(&e)),
+        Some("yaml") | Some("yml") => serde_yaml::to_string(document).map_err(|e| error(&e)),
+        Some("toml") => toml::Value::try_from(document)
+            .and_then(|document| toml::to_string(&document))
+            .map_err(|e| error(&e)),
+        _ => Err(Error::new(&format!(
+            "Unknown document format of `{}`, expected json, yaml or toml.",
+            path.to_str().unwrap()
+        ))),
+    };
+}
+
+fn read_document(filesystem: &dyn Filesystem, path: &Path) -> Result<Option<Value>> {
+    if !filesystem.is_file(path) {
+        return Ok(None);
+    }
+    let content = filesystem
+        .read_to_string(path)
+        .map_err(|e| Error::new(&format!("Failed to read `{}`.", path.to_str().unwrap())).with_inner_error(&e))?;
+    return parse(path, &content).map(Some);
+}
+
+/// Deep merge `fragment` into `document`. Objects are merged key by key, anything else replaces the
+/// value in the document. Every key that was added or replaced is pushed to `keys`.
+fn merge(document: &mut Map<String, Value>, fragment: &Map<String, Value>, path: &[String], keys: &mut Vec<MergedKey>) {
+    for (key, value) in fragment {
+        let mut key_path = path.to_vec();
+        key_path.push(key.clone());
+        match (document.get_mut(key), value) {
+            (Some(Value::Object(existing)), Value::Object(value)) => merge(existing, value, &key_path, keys),
+            (Some(existing), _) => {
+                if existing != value {
+                    keys.push(MergedKey {
+                        path: key_path,
+                        value: value.clone(),
+                        previous: Some(existing.clone()),
+                    });
+                    *existing = value.clone();
+                }
+            }
+            (None, _) => {
+                keys.push(MergedKey {
+                    path: key_path,
+                    value: value.clone(),
+                    previous: None,
+                });
+                document.insert(key.clone(), value.clone());
+            }
+        }
+    }
+}
+
+fn lookup<'a>(document: &'a Value, path: &[String]) -> Option<&'a Value> {
+    let mut current = document;
+    for key in path {
+        current = current.as_object()?.get(key)?;
+    }
+    return Some(current);
+}
+
+fn lookup_mut<'a>(document: &'a mut Value, path: &[String]) -> Option<&'a mut Value> {
+    let mut current = document;
+    for key in path {
+        current = current.as_object_mut()?.get_mut(key)?;
+    }
+    return Some(current);
+}
+
+fn read_fragment(link: &Link) -> Result<Map<String, Value>> {
+    return match read_document(&*link.filesystem, &link.target)? {
+        Some(Value::Object(fragment)) => Ok(fragment),
+        Some(_) => Err(Error::new(&format!(
+            "`{}` must be a document with keys at the top level.",
+            link.target.to_str().unwrap()
+        ))),
+        None => Err(Error::new(&format!("`{}` is not exists.", link.target.to_str().unwrap()))),
+    };
+}
+
+pub fn status(link: &Link, state: &State) -> Result<LinkStatus> {
+    let fragment = read_fragment(link)?;
+    let mut document = match read_document(&*link.filesystem, &link.path)? {
+        Some(Value::Object(document)) => document,
+        Some(_) => return Ok(LinkStatus::Modified("not-a-document".to_string())),
+        None => return Ok(LinkStatus::Inactive),
+    };
+    let mut keys = vec![];
+    merge(&mut document, &fragment, &[], &mut keys);
+    if keys.is_empty() {
+        return Ok(LinkStatus::Active);
+    }
+    debug!(
+        "Keys to merge into `{}`: {}.",
+        link.path.to_str().unwrap(),
+        keys.iter().map(|key| key.path.join(".")).collect::<Vec<String>>().join(", ")
+    );
+    let fragment_hash = hash_bytes(Value::Object(fragment).to_string().as_bytes());
+    return Ok(match state.get(&link.path) {
+        Some(entry) if entry.mode == MODE && entry.hash.as_ref() == Some(&fragment_hash) => {
+            LinkStatus::Modified("destination-modified".to_string())
+        }
+        Some(entry) if entry.mode == MODE => LinkStatus::Outdated("workspace-changed".to_string()),
+        _ => LinkStatus::Inactive,
+    });
+}
+
+pub fn create(link: &Link, state: &mut State, simulate: bool) -> Result<()> {
+    let fragment = read_fragment(link)?;
+    match status(link, state)? {
+        LinkStatus::Active => {
+            debug!("Document is merged: `{}`.", link.path.to_str().unwrap());
+            return Ok(());
+        }
+        LinkStatus::Modified(detail) if !link.force => {
+            return Err(Error::new(&format!(
+                "`{}` has local changes ({}), use `--force` to overwrite it.",
+                link.path.to_str().unwrap(),
+                detail
+            )));
+        }
+        _ => {}
+    }
+    let mut document = match read_document(&*link.filesystem, &link.path)? {
+        Some(Value::Object(document)) => document,
+        _ => Map::new(),
+    };
+    let mut keys = vec![];
+    merge(&mut document, &fragment, &[], &mut keys);
+    // Keep what the first merge recorded, a key idot added stays added even if it merges again.
+    let mut recorded = match state.get(&link.path) {
+        Some(entry) if entry.mode == MODE => entry.keys.clone().unwrap_or_default(),
+        _ => vec![],
+    };
+    for key in keys {
+        if !recorded.iter().any(|recorded| key.path.starts_with(&recorded.path)) {
+            recorded.push(key);
+        }
+    }
+    let document = Value::Object(document);
+    for key in recorded.iter_mut() {
+        if let Some(value) = lookup(&document, &key.path) {
+            key.value = value.clone();
+        }
+    }
+    debug!("Merge `{}` into `{}`.", link.target.to_str().unwrap(), link.path.to_str().unwrap());
+    if !simulate {
+        let content = serialize(&link.path, &document)?;
+        if let Some(parent) = link.path.parent() {
+            link.filesystem.mkdir(parent)
+                .map_err(|e| Error::new("Failed to create parent directory.").with_inner_error(&e))?;
+        }
+        link.filesystem.write(&link.path, content.as_bytes()).map_err(|e| Error::new("Failed to write document.").with_inner_error(&e))?;
+        state.set(
+            &link.path,
+            StateEntry {
+                mode: MODE.to_string(),
+                target: link.target.to_str().unwrap().to_string(),
+                hash: Some(hash_bytes(Value::Object(fragment).to_string().as_bytes())),
+                keys: Some(recorded),
+                ..Default::default()
+            },
+        );
+    }
+    return Ok(());
+}
+
+pub fn delete(link: &Link, state: &mut State, simulate: bool, report: &mut Report) -> Result<()> {
+    let keys = match state.get(&link.path) {
+        Some(entry) if entry.mode == MODE => entry.keys.clone().unwrap_or_default(),
+        _ => {
+            return Err(Error::new(&format!(
+                "`{}` was not merged by idot.",
+                link.path.to_str().unwrap()
+            )))
+        }
+    };
+    let mut document = read_document(&*link.filesystem, &link.path)?
+        .ok_or_else(|| Error::new(&format!("`{}` is not exists.", link.path.to_str().unwrap())))?;
+    for key in keys.iter().rev() {
+        let (last, parents) = match key.path.split_last() {
+            Some(split) => split,
+            None => continue,
+        };
+        if lookup(&document, &key.path) != Some(&key.value) {
+            report.push(Entry::Warning(format!(
+                "Keep `{}` in `{}`, it was changed after merge.",
+                key.path.join("."),
+                link.path.to_str().unwrap()
+            )));
+            continue;
+        }
+        if let Some(parent) = lookup_mut(&mut document, parents).and_then(|parent| parent.as_object_mut()) {
+            match key.previous {
+                Some(ref previous) => {
+                    parent.insert(last.clone(), previous.clone());
+                }
+                None => {
+                    parent.remove(last);
+                }
+            }
+        }
+    }
+    if !simulate {
+        let content = serialize(&link.path, &document)?;
+        link.filesystem.write(&link.path, content.as_bytes()).map_err(|e| Error::new("Failed to write document.").with_inner_error(&e))?;
+        state.remove(&link.path);
+    }
+    return Ok(());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    use serde_json::json;
+
+    use crate::facts::Facts;
+    use crate::filesystem::MemoryFilesystem;
+    use crate::linker::Linker;
+
+    const SETTINGS: &str = "links:\n  /home/me/settings.json:\n    target: settings.yaml\n    mode: merge\n";
+
+    fn linker(filesystem: &Arc<MemoryFilesystem>) -> Linker {
+        return Linker::builder("/dotfiles")
+            .configuration(serde_yaml::from_str(SETTINGS).unwrap())
+            .facts(Facts::new())
+            .filesystem(filesystem.clone())
+            .trust_all(true)
+            .build()
+            .unwrap();
+    }
+
+    fn document(filesystem: &MemoryFilesystem) -> Value {
+        return serde_json::from_slice(&filesystem.read(Path::new("/home/me/settings.json")).unwrap()).unwrap();
+    }
+
+    #[test]
+    fn merge_records_added_and_replaced_keys() {
+        let mut document = json!({"editor": {"font": "mono", "size": 12}, "theme": "light"});
+        let fragment = json!({"editor": {"size": 14, "tabs": 4}, "theme": "light"});
+        let mut keys = vec![];
+        merge(document.as_object_mut().unwrap(), fragment.as_object().unwrap(), &[], &mut keys);
+        assert_eq!(document, json!({"editor": {"font": "mono", "size": 14, "tabs": 4}, "theme": "light"}));
+        let path = |path: &[&str]| path.iter().map(|key| key.to_string()).collect::<Vec<String>>();
+        assert_eq!(
+            keys,
+            vec![
+                MergedKey { path: path(&["editor", "size"]), value: json!(14), previous: Some(json!(12)) },
+                MergedKey { path: path(&["editor", "tabs"]), value: json!(4), previous: None },
+            ]
+        );
+    }
+
+    #[test]
+    fn delete_restores_previous_values_and_removes_added_keys() {
+        let filesystem = Arc::new(MemoryFilesystem::new());
+        filesystem.add_file("/dotfiles/settings.yaml", b"editor:\n  size: 14\n  tabs: 4\n").unwrap();
+        filesystem.add_file("/home/me/settings.json", br#"{"editor": {"font": "mono", "size": 12}}"#).unwrap();
+        let linker = linker(&filesystem);
+        assert!(!linker.create().unwrap().has_failures());
+        assert_eq!(document(&filesystem), json!({"editor": {"font": "mono", "size": 14, "tabs": 4}}));
+        assert!(!linker.delete().unwrap().has_failures());
+        assert_eq!(document(&filesystem), json!({"editor": {"font": "mono", "size": 12}}));
+    }
+
+    #[test]
+    fn delete_keeps_keys_changed_after_merge() {
+        let filesystem = Arc::new(MemoryFilesystem::new());
+        filesystem.add_file("/dotfiles/settings.yaml", b"editor:\n  size: 14\n  tabs: 4\n").unwrap();
+        filesystem.add_file("/home/me/settings.json", br#"{"editor": {"font": "mono"}}"#).unwrap();
+        let linker = linker(&filesystem);
+        assert!(!linker.create().unwrap().has_failures());
+        filesystem.write(Path::new("/home/me/settings.json"), br#"{"editor": {"font": "mono", "size": 16, "tabs": 4}}"#).unwrap();
+        let report = linker.delete().unwrap();
+        assert!(report.entries.contains(&Entry::Warning(
+            "Keep `editor.size` in `/home/me/settings.json`, it was changed after merge.".to_string()
+        )));
+        assert_eq!(document(&filesystem), json!({"editor": {"font": "mono", "size": 16}}));
+    }
+}
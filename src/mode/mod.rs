@@ -0,0 +1,104 @@
+use std::io;
+use std::path::Path;
+
+use sha2::{Digest, Sha256};
+
+use crate::filesystem::Filesystem;
+use crate::linker::LinkStatus;
+use crate::state::{hash_bytes, StateEntry};
+
+pub mod block;
+pub mod copy;
+pub mod extract;
+pub mod generate;
+pub mod hardlink;
+pub mod merge;
+pub mod patch;
+pub mod template;
+
+/// Status of a file that idot writes itself, from the hash of what it would write now, the hash of
+/// what is on disk and the hash recorded when it was last written.
+pub fn managed_status(
+    expected_hash: Option<String>,
+    destination_hash: String,
+    entry: Option<&StateEntry>,
+    mode: &str,
+) -> LinkStatus {
+    if expected_hash.as_ref() == Some(&destination_hash) {
+        return LinkStatus::Active;
+    }
+    let recorded_hash = match entry {
+        Some(entry) if entry.mode == mode => entry.hash.clone(),
+        _ => None,
+    };
+    return match recorded_hash {
+        Some(ref hash) if *hash == destination_hash => LinkStatus::Outdated("workspace-changed".to_string()),
+        Some(ref hash) if expected_hash.as_ref() == Some(hash) => {
+            LinkStatus::Modified("destination-modified".to_string())
+        }
+        Some(_) => LinkStatus::Modified("both-changed".to_string()),
+        None => LinkStatus::Modified("destination-modified".to_string()),
+    };
+}
+
+/// Content hash of a file, a symbolic link or a whole directory tree, `None` if nothing is there.
+///
+/// The hash of a regular file is the hash of its content, as computed by `hash_bytes`.
+pub fn hash_path<P: AsRef<Path>>(filesystem: &dyn Filesystem, path: P) -> io::Result<Option<String>> {
+    let path = path.as_ref();
+    if !filesystem.actually_exists(path) {
+        return Ok(None);
+    }
+    if filesystem.is_file(path) && !filesystem.is_symbolic(path) {
+        return Ok(Some(hash_bytes(&filesystem.read(path)?)));
+    }
+    let mut hasher = Sha256::new();
+    hash_into(filesystem, path, &mut hasher)?;
+    return Ok(Some(format!("{:x}", hasher.finalize())));
+}
+
+fn hash_into(filesystem: &dyn Filesystem, path: &Path, hasher: &mut Sha256) -> io::Result<()> {
+    if filesystem.is_symbolic(path) {
+        hasher.update(b"l");
+        hasher.update(filesystem.read_link(path)?.to_string_lossy().as_bytes());
+    } else if filesystem.is_dir(path) {
+        hasher.update(b"d");
+        for child in filesystem.read_dir(path)? {
+            hasher.update(child.file_name().unwrap().to_string_lossy().as_bytes());
+            hasher.update([0]);
+            hash_into(filesystem, &child, hasher)?;
+        }
+    } else {
+        hasher.update(b"f");
+        hasher.update(filesystem.read(path)?);
+    }
+    hasher.update([0]);
+    return Ok(());
+}
+
+/// Copy a file or directory tree with its permissions, replacing whatever is at `destination`.
+pub fn copy_path<P: AsRef<Path>>(filesystem: &dyn Filesystem, source: P, destination: P) -> io::Result<()> {
+    let source = source.as_ref();
+    let destination = destination.as_ref();
+    remove_path(filesystem, destination)?;
+    if let Some(parent) = destination.parent() {
+        filesystem.mkdir(parent)?;
+    }
+    if filesystem.is_dir(source) {
+        filesystem.mkdir(destination)?;
+        for child in filesystem.read_dir(source)? {
+            copy_path(filesystem, &child, &destination.join(child.file_name().unwrap()))?;
+        }
+    } else {
+        filesystem.write(destination, &filesystem.read(source)?)?;
+    }
+    return filesystem.set_permissions(destination, filesystem.metadata(source)?.mode);
+}
+
+pub fn remove_path<P: AsRef<Path>>(filesystem: &dyn Filesystem, path: P) -> io::Result<()> {
+    let path = path.as_ref();
+    if !filesystem.actually_exists(path) {
+        return Ok(());
+    }
+    return filesystem.remove(path);
+}
@@ -0,0 +1,150 @@
+
+use log::debug;
+
+use crate::diff::{HunkResult, Patch};
+use crate::error::{Error, Result};
+use crate::linker::{Link, LinkStatus};
+use crate::report::{Entry, Report};
+use crate::state::{hash_bytes, State, StateEntry};
+
+pub const MODE: &str = "patch";
+
+fn read_patch(link: &Link) -> Result<Patch> {
+    let content = link.filesystem.read_to_string(&link.target)
+        .map_err(|e| Error::new("Failed to read patch.").with_inner_error(&e))?;
+    return Patch::parse(&content)
+        .map_err(|e| Error::new(&format!("Invalid patch `{}`.", link.target.to_str().unwrap())).with_inner_error(&e));
+}
+
+/// The reverse of the patch that was applied last time, if idot applied one.
+fn recorded_reverse(link: &Link, state: &State) -> Result<Option<Patch>> {
+    return match state.get(&link.path) {
+        Some(entry) if entry.mode == MODE => match entry.reverse {
+            Some(ref reverse) => Patch::parse(reverse).map(Some),
+            None => Ok(None),
+        },
+        _ => Ok(None),
+    };
+}
+
+/// Warn about hunks that didn't apply where the patch says.
+fn report_inexact(link: &Link, results: &[HunkResult], report: &mut Report) {
+    for (index, result) in results.iter().enumerate() {
+        if result.offset != 0 || result.fuzz != 0 {
+            report.push(Entry::Warning(format!(
+                "Hunk #{} of `{}` applied at line {} (offset {} lines, fuzz {}).",
+                index + 1,
+                link.target.to_str().unwrap(),
+                result.line,
+                result.offset,
+                result.fuzz
+            )));
+        }
+    }
+}
+
+pub fn status(link: &Link, state: &State) -> Result<LinkStatus> {
+    // Nothing to unapply, the file isn't installed (yet) or was removed.
+    if !link.filesystem.actually_exists(&link.path) {
+        return Ok(LinkStatus::Inactive);
+    }
+    if !link.filesystem.is_file(&link.path) {
+        return Ok(LinkStatus::Modified("not-a-file".to_string()));
+    }
+    let content = link.filesystem.read_to_string(&link.path).map_err(|e| Error::new("Failed to read file.").with_inner_error(&e))?;
+    let patch = read_patch(link)?;
+    let reverse = patch.reverse().to_string();
+    if let Some(entry) = state.get(&link.path).filter(|entry| entry.mode == MODE) {
+        if entry.hash.as_ref() == Some(&hash_bytes(content.as_bytes())) {
+            return Ok(if entry.reverse.as_ref() == Some(&reverse) {
+                LinkStatus::Active
+            } else {
+                LinkStatus::Outdated("workspace-changed".to_string())
+            });
+        }
+    }
+    if patch.reverse().apply_exactly(&content).is_ok() {
+        return Ok(LinkStatus::Active);
+    }
+    if let Some(recorded) = recorded_reverse(link, state)? {
+        if recorded.apply(&content).is_ok() {
+            return Ok(if recorded.to_string() == reverse {
+                LinkStatus::Active
+            } else {
+                LinkStatus::Outdated("workspace-changed".to_string())
+            });
+        }
+    }
+    if patch.apply(&content).is_ok() {
+        return Ok(LinkStatus::Inactive);
+    }
+    return Ok(LinkStatus::Modified("conflicting".to_string()));
+}
+
+pub fn create(link: &Link, state: &mut State, simulate: bool, report: &mut Report) -> Result<()> {
+    if !link.filesystem.is_file(&link.path) {
+        return Err(Error::new(&format!(
+            "`{}` is not exists, it must be installed before it can be patched.",
+            link.path.to_str().unwrap()
+        )));
+    }
+    let patch = read_patch(link)?;
+    let mut content = link.filesystem.read_to_string(&link.path).map_err(|e| Error::new("Failed to read file.").with_inner_error(&e))?;
+    match status(link, state)? {
+        LinkStatus::Active => {
+            debug!("Patch is applied: `{}`.", link.path.to_str().unwrap());
+            return Ok(());
+        }
+        LinkStatus::Modified(detail) => {
+            return Err(Error::new(&format!(
+                "`{}` doesn't apply to `{}` ({}).",
+                link.target.to_str().unwrap(),
+                link.path.to_str().unwrap(),
+                detail
+            )));
+        }
+        LinkStatus::Outdated(_) => {
+            debug!("Unapply the previous patch from `{}`.", link.path.to_str().unwrap());
+            if let Some(reverse) = recorded_reverse(link, state)? {
+                let (unpatched, results) = reverse.apply(&content)?;
+                report_inexact(link, &results, report);
+                content = unpatched;
+            }
+        }
+        LinkStatus::Inactive => {}
+    }
+    let (patched, results) = patch.apply(&content)?;
+    report_inexact(link, &results, report);
+    debug!("Apply `{}` to `{}`.", link.target.to_str().unwrap(), link.path.to_str().unwrap());
+    if !simulate {
+        link.filesystem.write(&link.path, patched.as_bytes()).map_err(|e| Error::new("Failed to write file.").with_inner_error(&e))?;
+        state.set(
+            &link.path,
+            StateEntry {
+                mode: MODE.to_string(),
+                target: link.target.to_str().unwrap().to_string(),
+                hash: Some(hash_bytes(patched.as_bytes())),
+                reverse: Some(patch.reverse().to_string()),
+                ..Default::default()
+            },
+        );
+    }
+    return Ok(());
+}
+
+pub fn delete(link: &Link, state: &mut State, simulate: bool, report: &mut Report) -> Result<()> {
+    let reverse = match recorded_reverse(link, state)? {
+        Some(reverse) => reverse,
+        None => read_patch(link)?.reverse(),
+    };
+    let content = link.filesystem.read_to_string(&link.path).map_err(|e| Error::new("Failed to read file.").with_inner_error(&e))?;
+    let (unpatched, results) = reverse
+        .apply(&content)
+        .map_err(|e| Error::new(&format!("Failed to unapply patch from `{}`.", link.path.to_str().unwrap())).with_inner_error(&e))?;
+    report_inexact(link, &results, report);
+    if !simulate {
+        link.filesystem.write(&link.path, unpatched.as_bytes()).map_err(|e| Error::new("Failed to write file.").with_inner_error(&e))?;
+        state.remove(&link.path);
+    }
+    return Ok(());
+}
@@ -0,0 +1,127 @@
+use std::io;
+use std::path::{Component, Path};
+use std::sync::Arc;
+
+use log::debug;
+use minijinja::{Environment, ErrorKind, UndefinedBehavior, Value};
+
+use crate::error::{Error, Result};
+use crate::filesystem::Filesystem;
+use crate::linker::{Link, LinkStatus};
+use crate::mode::{hash_path, managed_status, remove_path};
+use crate::state::{hash_bytes, State, StateEntry};
+use crate::variables::Variables;
+
+pub const MODE: &str = "template";
+
+/// Render a workspace template with the variables and facts.
+///
+/// Templates use Jinja syntax and can include other templates by their path in the workspace.
+pub fn render<P: AsRef<Path>>(filesystem: &Arc<dyn Filesystem>, workspace: P, target: P, variables: &Variables) -> Result<String> {
+    let workspace = workspace.as_ref();
+    let target = target.as_ref();
+    let name = target
+        .strip_prefix(workspace)
+        .map_err(|_| Error::new(&format!("`{}` is not in the workspace.", target.to_str().unwrap())))?
+        .to_str()
+        .unwrap()
+        .to_string();
+    let mut environment = Environment::new();
+    let (filesystem, root) = (filesystem.clone(), workspace.to_path_buf());
+    environment.set_loader(move |name| {
+        let name = Path::new(name);
+        if !name.components().all(|component| matches!(component, Component::Normal(_))) {
+            return Ok(None);
+        }
+        return match filesystem.read_to_string(&root.join(name)) {
+            Ok(source) => Ok(Some(source)),
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(minijinja::Error::new(ErrorKind::InvalidOperation, "could not read template").with_source(e)),
+        };
+    });
+    environment.set_keep_trailing_newline(true);
+    environment.set_undefined_behavior(UndefinedBehavior::Strict);
+    let template = environment
+        .get_template(&name)
+        .map_err(|e| Error::new(&format!("Failed to load template `{}`.", name)).with_inner_error(&e))?;
+    return template
+        .render(Value::from_serialize(variables.to_json()))
+        .map_err(|e| Error::new(&format!("Failed to render template `{}`.", name)).with_inner_error(&e));
+}
+
+pub fn status(link: &Link, state: &State) -> Result<LinkStatus> {
+    let destination_hash = match hash_path(&*link.filesystem, &link.path)
+        .map_err(|e| Error::new("Failed to read rendered file.").with_inner_error(&e))?
+    {
+        Some(hash) => hash,
+        None => return Ok(LinkStatus::Inactive),
+    };
+    let rendered = render(&link.filesystem, &link.workspace, &link.target, &link.variables)?;
+    return Ok(managed_status(
+        Some(hash_bytes(rendered.as_bytes())),
+        destination_hash,
+        state.get(&link.path),
+        MODE,
+    ));
+}
+
+pub fn create(link: &Link, state: &mut State, simulate: bool) -> Result<()> {
+    let rendered = render(&link.filesystem, &link.workspace, &link.target, &link.variables)?;
+    match status(link, state)? {
+        LinkStatus::Active => {
+            debug!("Rendered file is in sync: `{}`.", link.path.to_str().unwrap());
+        }
+        LinkStatus::Modified(detail) if !link.force => {
+            return Err(Error::new(&format!(
+                "`{}` has local changes ({}), use `--force` to overwrite it.",
+                link.path.to_str().unwrap(),
+                detail
+            )));
+        }
+        _ => {
+            debug!("Render `{}` to `{}`.", link.target.to_str().unwrap(), link.path.to_str().unwrap());
+            if !simulate {
+                remove_path(&*link.filesystem, &link.path).map_err(|e| Error::new("Failed to delete file.").with_inner_error(&e))?;
+                if let Some(parent) = link.path.parent() {
+                    link.filesystem.mkdir(parent)
+                        .map_err(|e| Error::new("Failed to create parent directory.").with_inner_error(&e))?;
+                }
+                link.filesystem.write(&link.path, rendered.as_bytes())
+                    .map_err(|e| Error::new("Failed to write rendered file.").with_inner_error(&e))?;
+            }
+        }
+    }
+    if !simulate {
+        state.set(
+            &link.path,
+            StateEntry {
+                mode: MODE.to_string(),
+                target: link.target.to_str().unwrap().to_string(),
+                hash: Some(hash_bytes(rendered.as_bytes())),
+                ..Default::default()
+            },
+        );
+    }
+    return Ok(());
+}
+
+pub fn delete(link: &Link, state: &mut State, simulate: bool) -> Result<()> {
+    match status(link, state)? {
+        LinkStatus::Inactive => {
+            return Err(Error::new(&format!("`{}` is not exists.", link.path.to_str().unwrap())));
+        }
+        LinkStatus::Modified(detail) => {
+            return Err(Error::new(&format!(
+                "`{}` has local changes ({}).",
+                link.path.to_str().unwrap(),
+                detail
+            )));
+        }
+        _ => {}
+    }
+    if !simulate {
+        link.filesystem.remove(&link.path).map_err(|e| Error::new("Failed to delete rendered file.").with_inner_error(&e))?;
+        state.remove(&link.path);
+    }
+    return Ok(());
+}
@@ -0,0 +1,137 @@
+use std::path::PathBuf;
+
+use colored::*;
+use idot::linker::describe;
+use idot::mode::patch;
+use idot::report::{Entry, LinkState, Report, Status, TaskState};
+use idot::task::TaskStatus;
+use idot::trust::CommandChange;
+use idot::LinkStatus;
+use log::{error, info, warn};
+
+pub fn print_report(report: &Report) {
+    for entry in &report.entries {
+        match entry {
+            Entry::Failed { .. } => error!("{}", entry),
+            Entry::Warning(_) | Entry::TaskUntrusted { .. } => warn!("{}", entry),
+            _ => info!("{}", entry),
+        }
+    }
+}
+
+/// Links, with the locations of a target-keyed entry listed under it, then tasks.
+pub fn print_status(status: &Status) {
+    let mut reported: Vec<&PathBuf> = vec![];
+    for link in &status.links {
+        if !link.shared {
+            if let Some(line) = status_line(link, false) {
+                info!("{}", line);
+            }
+            continue;
+        }
+        if reported.contains(&&link.target) {
+            continue;
+        }
+        reported.push(&link.target);
+        let locations: Vec<&LinkState> = status
+            .links
+            .iter()
+            .filter(|location| location.shared && location.target == link.target)
+            .collect();
+        info!("`{}` ({} locations):", link.source, locations.len());
+        for location in locations {
+            if let Some(line) = status_line(location, true) {
+                info!("  {}", line);
+            }
+        }
+    }
+    for task in &status.tasks {
+        info!("{}", task_line(task));
+    }
+}
+
+/// One line of `status`, without the source for a location listed under its shared target.
+fn status_line(link: &LinkState, grouped: bool) -> Option<String> {
+    let source = if grouped {
+        String::new()
+    } else {
+        format!(" -> `{}`", link.source)
+    };
+    if let Some(ref clause) = link.not_applicable {
+        return Some(format!(
+            "`{}`{}. {}",
+            link.path.to_str().unwrap().dimmed(),
+            source,
+            format!("(not applicable: {})", clause).dimmed(),
+        ));
+    }
+    let mut selected = match link.alternate {
+        Some(ref reason) => format!(" (alternate: {})", reason).dimmed().to_string(),
+        None => String::new(),
+    };
+    if let Some(ref layout) = link.layout {
+        selected.push_str(&format!(" (layout: {})", layout).dimmed().to_string());
+    }
+    let (path, label) = match link.status {
+        Some(Ok(LinkStatus::Active)) => (link.path.to_str().unwrap().green(), label(&link.mode, true)),
+        Some(Ok(LinkStatus::Outdated(ref detail))) => (link.path.to_str().unwrap().yellow(), detail.clone()),
+        Some(Ok(LinkStatus::Modified(ref detail))) => (link.path.to_str().unwrap().red(), detail.clone()),
+        Some(Ok(LinkStatus::Inactive)) | None => (link.path.to_str().unwrap().red(), label(&link.mode, false)),
+        Some(Err(ref error)) => {
+            error!("Failed to check {}: `{}`. {}", describe(&link.mode), &link.name, error);
+            return None;
+        }
+    };
+    let mode = if link.contents {
+        format!(" [contents: {}]", label)
+    } else if link.mode == idot::linker::SYMLINK_MODE {
+        String::new()
+    } else {
+        format!(" [{}: {}]", link.mode, label)
+    };
+    return Some(format!("`{}`{}.{}{}", path, source, mode, selected));
+}
+
+/// Status label of an active or inactive link in `status`.
+fn label(mode: &str, active: bool) -> String {
+    return match (mode, active) {
+        (patch::MODE, true) => "applied",
+        (patch::MODE, false) => "applicable",
+        (_, true) => "in-sync",
+        (_, false) => "missing",
+    }
+    .to_string();
+}
+
+/// The status line of a task, in the format of links.
+fn task_line(task: &TaskState) -> String {
+    let source = format!("$({})", &task.command);
+    if let Some(ref clause) = task.not_applicable {
+        return format!(
+            "`{}` -> `{}`. {}",
+            task.name.dimmed(),
+            source,
+            format!("(not applicable: {})", clause).dimmed()
+        );
+    }
+    let (name, label) = match task.status {
+        _ if !task.trusted => (task.name.yellow(), "pending (untrusted)".to_string()),
+        Some(TaskStatus::Done(_)) => (task.name.green(), "done".to_string()),
+        Some(TaskStatus::Skipped(ref reason)) => (task.name.dimmed(), format!("skipped, {}", reason)),
+        Some(TaskStatus::Pending) | None => (task.name.red(), "pending".to_string()),
+    };
+    return format!("`{}` -> `{}`. [task: {}]", name, source, label);
+}
+
+/// How the commands changed since they were last allowed, as a diff.
+pub fn print_changes(changes: &[CommandChange]) {
+    for change in changes {
+        info!("{}", change.location);
+        if let Some(ref previous) = change.previous {
+            info!("{}", format!("- {}", previous).red());
+        }
+        if let Some(ref current) = change.current {
+            info!("{}", format!("+ {}", current).green());
+        }
+    }
+}
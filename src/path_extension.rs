@@ -1,4 +1,3 @@
-use std::fs::{self};
 use std::io::Result;
 use std::path::{Path, PathBuf};
 
@@ -6,30 +5,24 @@ use path_absolutize::Absolutize;
 use pathdiff::diff_paths;
 use shellexpand::tilde;
 
+use crate::filesystem::{Filesystem, RealFilesystem};
+
 pub trait PathExtension {
     fn is_symbolic(&self) -> bool;
     fn actually_exists(&self) -> bool;
     fn absolutize(&self) -> Result<PathBuf>;
+    /// The path relative to `base` unless it is absolute, after expanding a leading `~`.
+    fn absolutize_in<P: AsRef<Path>>(&self, base: P) -> Result<PathBuf>;
     fn relative_to<P: AsRef<Path>>(&self, path: P) -> Option<PathBuf>;
 }
 
 impl PathExtension for Path {
     fn is_symbolic(&self) -> bool {
-        return if self.actually_exists() {
-            match self
-                .symlink_metadata()
-                .map(|metadata| metadata.file_type().is_symlink())
-            {
-                Ok(result) => result,
-                Err(_) => false,
-            }
-        } else {
-            false
-        };
+        return RealFilesystem.is_symbolic(self);
     }
 
     fn actually_exists(&self) -> bool {
-        return self.exists() || fs::read_link(&self).is_ok();
+        return RealFilesystem.actually_exists(self);
     }
 
     fn absolutize(&self) -> Result<PathBuf> {
@@ -38,9 +31,14 @@ impl PathExtension for Path {
         return Ok(path);
     }
 
+    fn absolutize_in<P: AsRef<Path>>(&self, base: P) -> Result<PathBuf> {
+        let path = tilde(self.to_str().unwrap());
+        return PathExtension::absolutize(&base.as_ref().join(path.as_ref()));
+    }
+
     fn relative_to<P: AsRef<Path>>(&self, path: P) -> Option<PathBuf> {
         let path = PathExtension::absolutize(path.as_ref()).ok()?;
-        return diff_paths(&self, &path);
+        return diff_paths(self, &path);
     }
 }
 
@@ -57,6 +55,10 @@ impl PathExtension for PathBuf {
         PathExtension::absolutize(self.as_path())
     }
 
+    fn absolutize_in<P: AsRef<Path>>(&self, base: P) -> Result<PathBuf> {
+        self.as_path().absolutize_in(base)
+    }
+
     fn relative_to<P: AsRef<Path>>(&self, path: P) -> Option<PathBuf> {
         self.as_path().relative_to(path)
     }
@@ -0,0 +1,268 @@
+use std::path::{Path, PathBuf};
+
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use ignore::Match;
+use regex::{Captures, Regex};
+
+use crate::alternate;
+use crate::error::{Error, Result};
+use crate::filesystem::Filesystem;
+
+/// File in the workspace root with paths that glob and pattern links never match, in gitignore syntax.
+pub const IGNORE_FILE: &str = ".idotignore";
+
+/// Whether a link path or target is a glob like `bin/*` that expands to one link per match.
+pub fn is_glob(path: &str) -> bool {
+    return path.contains(['*', '?']);
+}
+
+/// Expand a glob link, every `*`, `**` or `?` in `link` is replaced by what the same wildcard of
+/// `target` matched.
+pub fn expand_glob(filesystem: &dyn Filesystem, workspace: &Path, link: &str, target: &str, exclude: &[String]) -> Result<Vec<(String, String)>> {
+    let regex = Regex::new(&glob_to_regex(target))
+        .map_err(|e| Error::new(&format!("Invalid glob `{}`.", target)).with_inner_error(&e))?;
+    let wildcard = Regex::new(r"\*\*|\*|\?").unwrap();
+    return expand(filesystem, workspace, &regex, exclude, |captures| {
+        let mut index = 0;
+        return wildcard
+            .replace_all(link, |_: &Captures| {
+                index += 1;
+                return captures.get(index).map_or("", |capture| capture.as_str()).to_string();
+            })
+            .to_string();
+    });
+}
+
+/// Expand a link with a `pattern`, a regular expression matching whole paths relative to the
+/// workspace. `$1` or `${name}` in `link` are replaced by the capture groups.
+pub fn expand_pattern(filesystem: &dyn Filesystem, workspace: &Path, link: &str, pattern: &str, exclude: &[String]) -> Result<Vec<(String, String)>> {
+    let regex = Regex::new(&format!("^(?:{})$", pattern))
+        .map_err(|e| Error::new(&format!("Invalid pattern `{}`.", pattern)).with_inner_error(&e))?;
+    let reference = Regex::new(r"\$(\d+)|\$\{(\w+)\}").unwrap();
+    return expand(filesystem, workspace, &regex, exclude, |captures| {
+        return reference
+            .replace_all(link, |reference: &Captures| {
+                let name = reference.get(1).unwrap_or_else(|| reference.get(2).unwrap()).as_str();
+                let capture = match name.parse::<usize>() {
+                    Ok(index) => captures.get(index),
+                    Err(_) => captures.name(name),
+                };
+                return match capture {
+                    Some(capture) => capture.as_str().to_string(),
+                    // Not a capture group, leave it to the variables.
+                    None => reference[0].to_string(),
+                };
+            })
+            .to_string();
+    });
+}
+
+/// Every workspace path matching `regex` with the link path made from its captures. Paths inside
+/// an already matched directory are skipped, the directory is linked as a whole. Alternates are
+/// matched once by their base path, the linker selects one.
+fn expand<F: Fn(&Captures) -> String>(filesystem: &dyn Filesystem, workspace: &Path, regex: &Regex, exclude: &[String], link: F) -> Result<Vec<(String, String)>> {
+    let exclude = exclude_set(exclude)?;
+    let mut result = vec![];
+    let mut matched: Vec<PathBuf> = vec![];
+    for path in walk(filesystem, workspace)? {
+        if exclude.is_match(&path) {
+            continue;
+        }
+        let path = alternate::base_path(path);
+        if matched.iter().any(|directory| path.starts_with(directory)) {
+            continue;
+        }
+        let name = path.to_str().unwrap();
+        if let Some(captures) = regex.captures(name) {
+            result.push((link(&captures), name.to_string()));
+            matched.push(path.clone());
+        }
+    }
+    return Ok(result);
+}
+
+/// Files in `directory` of the workspace and below it, relative to the workspace.
+pub fn files(filesystem: &dyn Filesystem, workspace: &Path, directory: &str) -> Result<Vec<PathBuf>> {
+    return Ok(walk(filesystem, workspace)?
+        .into_iter()
+        .filter(|path| path.starts_with(directory))
+        .filter(|path| {
+            let path = workspace.join(path);
+            return filesystem.is_symbolic(&path) || !filesystem.is_dir(&path);
+        })
+        .collect());
+}
+
+fn exclude_set(exclude: &[String]) -> Result<GlobSet> {
+    let mut builder = GlobSetBuilder::new();
+    for glob in exclude {
+        builder.add(Glob::new(glob).map_err(|e| Error::new(&format!("Invalid exclude `{}`.", glob)).with_inner_error(&e))?);
+    }
+    return builder.build().map_err(|e| Error::new("Invalid exclude.").with_inner_error(&e));
+}
+
+/// Paths in the workspace relative to it, sorted, without `.git` and what `.idotignore` files ignore.
+fn walk(filesystem: &dyn Filesystem, workspace: &Path) -> Result<Vec<PathBuf>> {
+    let mut paths = vec![];
+    visit(filesystem, workspace, workspace, &mut vec![], &mut paths)?;
+    return Ok(paths);
+}
+
+/// Collect the paths below `directory`, the rules of its `.idotignore` apply to them after the
+/// rules of its parents. Symbolic links to directories are not followed.
+fn visit(filesystem: &dyn Filesystem, workspace: &Path, directory: &Path, ignores: &mut Vec<Gitignore>, paths: &mut Vec<PathBuf>) -> Result<()> {
+    let ignore_file = directory.join(IGNORE_FILE);
+    let has_ignore_file = filesystem.is_file(&ignore_file);
+    if has_ignore_file {
+        ignores.push(ignore_rules(filesystem, directory, &ignore_file)?);
+    }
+    let entries = filesystem
+        .read_dir(directory)
+        .map_err(|e| Error::new("Failed to read workspace.").with_inner_error(&e))?;
+    for path in entries {
+        let name = path.file_name().unwrap();
+        if name == ".git" || name == IGNORE_FILE {
+            continue;
+        }
+        let is_dir = filesystem.symlink_metadata(&path).map(|metadata| metadata.is_dir()).unwrap_or_default();
+        if is_ignored(ignores, &path, is_dir) {
+            continue;
+        }
+        paths.push(path.strip_prefix(workspace).unwrap().to_path_buf());
+        if is_dir {
+            visit(filesystem, workspace, &path, ignores, paths)?;
+        }
+    }
+    if has_ignore_file {
+        ignores.pop();
+    }
+    return Ok(());
+}
+
+fn ignore_rules(filesystem: &dyn Filesystem, directory: &Path, ignore_file: &Path) -> Result<Gitignore> {
+    let invalid = |e: &dyn std::error::Error| Error::new(&format!("Invalid `{}`.", ignore_file.to_str().unwrap())).with_inner_error(e);
+    let content = filesystem.read_to_string(ignore_file).map_err(|e| invalid(&e))?;
+    let mut builder = GitignoreBuilder::new(directory);
+    for line in content.lines() {
+        builder.add_line(Some(ignore_file.to_path_buf()), line).map_err(|e| invalid(&e))?;
+    }
+    return builder.build().map_err(|e| invalid(&e));
+}
+
+/// Whether the nearest `.idotignore` with a rule for `path` ignores it.
+fn is_ignored(ignores: &[Gitignore], path: &Path, is_dir: bool) -> bool {
+    for ignore in ignores.iter().rev() {
+        match ignore.matched(path, is_dir) {
+            Match::Ignore(_) => return true,
+            Match::Whitelist(_) => return false,
+            Match::None => {}
+        }
+    }
+    return false;
+}
+
+/// `**` matches across directories, `*` and `?` within one. Every wildcard is a capture group.
+fn glob_to_regex(glob: &str) -> String {
+    let mut regex = String::from("^");
+    let mut chars = glob.chars().peekable();
+    while let Some(char) = chars.next() {
+        match char {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                regex.push_str("(.*)");
+            }
+            '*' => regex.push_str("([^/]*)"),
+            '?' => regex.push_str("([^/])"),
+            _ => regex.push_str(&regex::escape(&char.to_string())),
+        }
+    }
+    regex.push('$');
+    return regex;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::filesystem::MemoryFilesystem;
+
+    const WORKSPACE: &str = "/dotfiles";
+
+    fn workspace(files: &[&str]) -> MemoryFilesystem {
+        let filesystem = MemoryFilesystem::new();
+        for file in files {
+            filesystem.add_file(Path::new(WORKSPACE).join(file), b"").unwrap();
+        }
+        return filesystem;
+    }
+
+    fn links(links: &[(&str, &str)]) -> Vec<(String, String)> {
+        return links.iter().map(|(link, target)| (link.to_string(), target.to_string())).collect();
+    }
+
+    #[test]
+    fn glob_to_regex_captures_every_wildcard() {
+        assert_eq!(glob_to_regex("bin/*.sh"), r"^bin/([^/]*)\.sh$");
+        assert_eq!(glob_to_regex("config/**/?.conf"), r"^config/(.*)/([^/])\.conf$");
+    }
+
+    #[test]
+    fn glob_fills_link_wildcards_with_captures() {
+        let filesystem = workspace(&["bin/a.sh", "bin/b.sh", "bin/c.py", "bin/sub/d.sh"]);
+        let expanded = expand_glob(&filesystem, Path::new(WORKSPACE), "~/.local/bin/*", "bin/*.sh", &[]).unwrap();
+        assert_eq!(expanded, links(&[("~/.local/bin/a", "bin/a.sh"), ("~/.local/bin/b", "bin/b.sh")]));
+    }
+
+    #[test]
+    fn glob_links_matched_directories_as_a_whole_and_skips_excluded_paths() {
+        let filesystem = workspace(&["config/git/config", "config/nvim/init.vim", "config/old.bak"]);
+        let exclude = vec!["config/*.bak".to_string()];
+        let expanded = expand_glob(&filesystem, Path::new(WORKSPACE), "~/.config/*", "config/*", &exclude).unwrap();
+        assert_eq!(expanded, links(&[("~/.config/git", "config/git"), ("~/.config/nvim", "config/nvim")]));
+    }
+
+    #[test]
+    fn glob_matches_alternates_by_base_path() {
+        let filesystem = workspace(&["home/.gitconfig##os.linux", "home/.gitconfig##os.macos"]);
+        let expanded = expand_glob(&filesystem, Path::new(WORKSPACE), "~/*", "home/*", &[]).unwrap();
+        assert_eq!(expanded, links(&[("~/.gitconfig", "home/.gitconfig")]));
+    }
+
+    #[test]
+    fn pattern_replaces_numbered_and_named_captures() {
+        let filesystem = workspace(&["themes/dark.conf", "themes/light.conf", "themes/README.md"]);
+        let link = "${HOME}/.config/$1/${name}.conf";
+        let expanded = expand_pattern(&filesystem, Path::new(WORKSPACE), link, r"themes/(?P<name>\w+)\.conf", &[]).unwrap();
+        assert_eq!(
+            expanded,
+            links(&[("${HOME}/.config/dark/dark.conf", "themes/dark.conf"), ("${HOME}/.config/light/light.conf", "themes/light.conf")])
+        );
+    }
+
+    #[test]
+    fn invalid_pattern_fails() {
+        let filesystem = workspace(&[]);
+        let error = expand_pattern(&filesystem, Path::new(WORKSPACE), "~/$1", "(", &[]).unwrap_err();
+        assert!(error.to_string().starts_with("Invalid pattern `(`."));
+    }
+
+    #[test]
+    fn walk_applies_nested_ignore_files() {
+        let filesystem = workspace(&[
+            ".git/config",
+            "README.md",
+            "keep.md",
+            "build/out",
+            "debug.log",
+            "nvim/init.vim",
+            "nvim/debug.log",
+            "nvim/notes.md",
+        ]);
+        filesystem.add_file("/dotfiles/.idotignore", b"*.md\n!keep.md\nbuild/\n").unwrap();
+        filesystem.add_file("/dotfiles/nvim/.idotignore", b"*.log\n!*.md\n").unwrap();
+        let paths = walk(&filesystem, Path::new(WORKSPACE)).unwrap();
+        let expected: Vec<PathBuf> = ["debug.log", "keep.md", "nvim", "nvim/init.vim", "nvim/notes.md"].iter().map(PathBuf::from).collect();
+        assert_eq!(paths, expected);
+    }
+}
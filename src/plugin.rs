@@ -0,0 +1,92 @@
+use std::env;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use idot::hook;
+use idot::{Error, GroupConfiguration, Result};
+
+/// Executables named `idot-<name>` on `PATH` are run as `idot <name>`.
+pub const PREFIX: &str = "idot-";
+
+/// Every plugin on `PATH` by name, the first one wins like for commands, sorted by name.
+pub fn discover() -> Vec<(String, PathBuf)> {
+    let mut plugins: Vec<(String, PathBuf)> = vec![];
+    let path = env::var_os("PATH").unwrap_or_default();
+    for directory in env::split_paths(&path) {
+        let entries = match fs::read_dir(&directory) {
+            Ok(entries) => entries,
+            Err(_) => continue,
+        };
+        let mut found: Vec<(String, PathBuf)> = entries
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| {
+                let name = entry.file_name().to_str()?.strip_prefix(PREFIX)?.to_string();
+                return Some((name, entry.path()));
+            })
+            .filter(|(name, path)| !name.is_empty() && is_executable(path))
+            .collect();
+        found.sort();
+        for (name, path) in found {
+            if !plugins.iter().any(|(existing, _)| *existing == name) {
+                plugins.push((name, path));
+            }
+        }
+    }
+    plugins.sort();
+    return plugins;
+}
+
+fn is_executable(path: &Path) -> bool {
+    return fs::metadata(path)
+        .map(|metadata| metadata.is_file() && metadata.permissions().mode() & 0o111 != 0)
+        .unwrap_or(false);
+}
+
+/// Plugins for the help, one per line.
+pub fn help(plugins: &[(String, PathBuf)]) -> Option<String> {
+    if plugins.is_empty() {
+        return None;
+    }
+    let width = plugins.iter().map(|(name, _)| name.len()).max().unwrap_or(0);
+    let mut help = String::from("PLUGINS:");
+    for (name, path) in plugins {
+        help.push_str(&format!("\n    {:width$}    {}", name, path.to_str().unwrap(), width = width));
+    }
+    return Some(help);
+}
+
+/// Run the plugin `name` with `arguments`. It gets the workspace, the configuration file and the
+/// loaded configuration as JSON in `IDOT_WORKSPACE`, `IDOT_CONFIGURATION` and
+/// `IDOT_CONFIGURATION_JSON`, the last two only when the workspace has a configuration.
+pub fn run(
+    name: &str,
+    arguments: &[&str],
+    workspace: &Path,
+    configuration: Option<(PathBuf, GroupConfiguration)>,
+    simulate: bool,
+) -> Result<()> {
+    let path = discover()
+        .into_iter()
+        .find(|(plugin, _)| plugin == name)
+        .map(|(_, path)| path)
+        .ok_or_else(|| Error::new(&format!("Unknown command `{}`, and no `{}{}` on PATH.", name, PREFIX, name)))?;
+    let mut command = Command::new(&path);
+    command
+        .args(arguments)
+        .env("IDOT_WORKSPACE", workspace)
+        .env("IDOT_SIMULATE", hook::flag(simulate));
+    if let Some((path, configuration)) = configuration {
+        let json = serde_json::to_string(&configuration)
+            .map_err(|e| Error::new("Failed to serialize configuration.").with_inner_error(&e))?;
+        command.env("IDOT_CONFIGURATION", path).env("IDOT_CONFIGURATION_JSON", json);
+    }
+    let status = command
+        .status()
+        .map_err(|e| Error::new(&format!("Failed to run `{}`.", path.to_str().unwrap())).with_inner_error(&e))?;
+    if !status.success() {
+        return Err(Error::new(&format!("Plugin `{}` failed, {}.", name, status)));
+    }
+    return Ok(());
+}
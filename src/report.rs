@@ -0,0 +1,114 @@
+use std::fmt;
+use std::path::PathBuf;
+
+use crate::error::Result;
+use crate::linker::{describe, LinkStatus};
+use crate::task::TaskStatus;
+
+/// What an operation of the `Linker` did, in the order it happened.
+///
+/// An operation goes on when one link, hook or task fails, the failure is an entry of the report.
+/// Errors that stop the whole operation are returned instead.
+#[derive(Debug, Default)]
+pub struct Report {
+    pub entries: Vec<Entry>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Entry {
+    /// A link was created, or would be with `simulate`.
+    Created { mode: String, path: PathBuf, source: String },
+    Deleted { mode: String, path: PathBuf },
+    /// A symbolic link into the workspace that no link creates any more was deleted.
+    Cleaned { path: PathBuf, target: PathBuf },
+    /// A file was moved into the workspace.
+    Adopted { path: PathBuf, target: PathBuf },
+    /// A target was renamed in the workspace.
+    Moved { from: PathBuf, to: PathBuf },
+    HookRun { event: String, command: String },
+    TaskRun { name: String, command: String },
+    /// A task was not checked or run with `simulate`, because its commands are not allowed.
+    TaskUntrusted { name: String, command: String },
+    Warning(String),
+    /// `action` failed for `name`, e.g. `create symbolic link` for a link.
+    Failed { action: String, name: String, message: String },
+}
+
+impl Report {
+    pub fn new() -> Self {
+        return Report { entries: vec![] };
+    }
+
+    pub fn push(&mut self, entry: Entry) {
+        self.entries.push(entry);
+    }
+
+    pub fn fail(&mut self, action: String, name: &str, message: &dyn fmt::Display) {
+        self.entries.push(Entry::Failed {
+            action,
+            name: name.to_string(),
+            message: message.to_string(),
+        });
+    }
+
+    pub fn has_failures(&self) -> bool {
+        return self.entries.iter().any(|entry| matches!(entry, Entry::Failed { .. }));
+    }
+}
+
+impl fmt::Display for Entry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return match self {
+            Entry::Created { mode, path, source } => {
+                write!(f, "Create {}: `{}` -> `{}`.", describe(mode), path.to_str().unwrap(), source)
+            }
+            Entry::Deleted { mode, path } => write!(f, "Delete {}: `{}`.", describe(mode), path.to_str().unwrap()),
+            Entry::Cleaned { path, target } => {
+                write!(f, "Clean symbolic link: `{}` -> `{}`.", path.to_str().unwrap(), target.to_str().unwrap())
+            }
+            Entry::Adopted { path, target } => write!(f, "Adopt `{}` as `{}`.", path.to_str().unwrap(), target.to_str().unwrap()),
+            Entry::Moved { from, to } => write!(f, "Move `{}` to `{}`.", from.to_str().unwrap(), to.to_str().unwrap()),
+            Entry::HookRun { event, command } => write!(f, "Run {} hook: `{}`.", event, command),
+            Entry::TaskRun { name, command } => write!(f, "Run task: `{}` -> `$({})`.", name, command),
+            Entry::TaskUntrusted { name, command } => {
+                write!(f, "Task: `{}` -> `$({})`. [pending (untrusted)]", name, command)
+            }
+            Entry::Warning(message) => write!(f, "{}", message),
+            Entry::Failed { action, name, message } => write!(f, "Failed to {}: `{}`. {}", action, name, message),
+        };
+    }
+}
+
+/// The links and tasks of the configuration with their status.
+pub struct Status {
+    pub links: Vec<LinkState>,
+    pub tasks: Vec<TaskState>,
+}
+
+pub struct LinkState {
+    pub name: String,
+    pub path: PathBuf,
+    pub target: PathBuf,
+    /// What the link shows as its target, e.g. `$(command)` for a generated file.
+    pub source: String,
+    pub mode: String,
+    pub contents: bool,
+    /// The link is one of the locations of a target-keyed entry.
+    pub shared: bool,
+    /// The clause of `when` that is false.
+    pub not_applicable: Option<String>,
+    /// Why an alternate file was selected.
+    pub alternate: Option<String>,
+    pub layout: Option<String>,
+    /// Not checked for links that aren't applicable.
+    pub status: Option<Result<LinkStatus>>,
+}
+
+pub struct TaskState {
+    pub name: String,
+    pub command: String,
+    pub not_applicable: Option<String>,
+    pub trusted: bool,
+    /// Not checked for tasks that aren't applicable or trusted, their guards don't run.
+    pub status: Option<TaskStatus>,
+}
@@ -0,0 +1,215 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use log::{debug, info};
+use rhai::{Dynamic, Engine, EvalAltResult, Map, Scope};
+
+use crate::error::{Error, Result};
+use crate::filesystem::Filesystem;
+use crate::path_extension::PathExtension;
+use crate::variables::Variables;
+
+/// Scripts are stopped after this many operations, so a loop can't hang idot.
+const MAX_OPERATIONS: u64 = 1_000_000;
+
+/// A Rhai script, written inline in the configuration or in a `.rhai` file of the workspace.
+///
+/// Scripts run in-process without access to the shell. They see the variables and facts as
+/// constants, `facts` and `env` as maps, and can use the path helpers `exists`, `is_dir`, `is_link`,
+/// `join`, `parent`, `file_name`, `expand` and `read`. Only hook scripts can `write` and `mkdir`.
+#[derive(Debug, Clone)]
+pub struct Script {
+    /// The file name, or `script` for an inline script.
+    pub name: String,
+    pub source: String,
+}
+
+/// Whether `script` names a `.rhai` file rather than being the script itself.
+pub fn is_file(script: &str) -> bool {
+    return script.ends_with(".rhai") && !script.contains(char::is_whitespace);
+}
+
+impl Script {
+    pub fn load(filesystem: &dyn Filesystem, workspace: &Path, script: &str) -> Result<Script> {
+        if !is_file(script) {
+            return Ok(Script {
+                name: "script".to_string(),
+                source: script.to_string(),
+            });
+        }
+        let source = filesystem
+            .read_to_string(&workspace.join(script))
+            .map_err(|e| Error::new(&format!("Failed to read script `{}`.", script)).with_inner_error(&e))?;
+        return Ok(Script {
+            name: script.to_string(),
+            source,
+        });
+    }
+
+    fn evaluate(&self, engine: &Engine, scope: &mut Scope) -> Result<Dynamic> {
+        return engine
+            .eval_with_scope::<Dynamic>(scope, &self.source)
+            .map_err(|e| Error::new(&format!("Failed to run script `{}`.", &self.name)).with_inner_error(&e));
+    }
+
+    /// Variables from the object map the script returns.
+    pub fn variables(&self, filesystem: &Arc<dyn Filesystem>, directory: &Path, variables: &Variables) -> Result<Vec<(String, String)>> {
+        let engine = engine(filesystem, directory, variables, None);
+        let value = self.evaluate(&engine, &mut scope(variables)?)?;
+        let map = value.try_cast::<Map>().ok_or_else(|| {
+            Error::new(&format!("Script `{}` must return an object map of variables.", &self.name))
+        })?;
+        return Ok(map.into_iter().map(|(name, value)| (name.to_string(), value.to_string())).collect());
+    }
+
+    /// The clause that made the condition false, the script name, if it didn't return `true`.
+    pub fn failing_clause(&self, filesystem: &Arc<dyn Filesystem>, directory: &Path, variables: &Variables) -> Result<Option<String>> {
+        let engine = engine(filesystem, directory, variables, None);
+        let value = self.evaluate(&engine, &mut scope(variables)?)?;
+        let value = value
+            .as_bool()
+            .map_err(|_| Error::new(&format!("Script `{}` must return a boolean.", &self.name)))?;
+        return Ok(if value { None } else { Some(self.name.clone()) });
+    }
+
+    /// Run the script as a hook. The hook environment is the `plan` map, `IDOT_LINK` as `plan.link`.
+    pub fn run(
+        &self,
+        filesystem: &Arc<dyn Filesystem>,
+        directory: &Path,
+        variables: &Variables,
+        environment: &[(String, String)],
+        simulate: bool,
+    ) -> Result<()> {
+        let engine = engine(filesystem, directory, variables, Some(simulate));
+        let mut scope = scope(variables)?;
+        let mut plan = Map::new();
+        for (name, value) in environment {
+            let name = name.strip_prefix("IDOT_").unwrap_or(name).to_lowercase();
+            let value = match name.as_str() {
+                "simulate" | "changed" => Dynamic::from(value == "1"),
+                _ => Dynamic::from(value.clone()),
+            };
+            plan.insert(name.into(), value);
+        }
+        scope.push_constant("plan", plan);
+        let _ = self.evaluate(&engine, &mut scope)?;
+        return Ok(());
+    }
+}
+
+/// The variables and facts as constants, where their names are identifiers.
+fn scope(variables: &Variables) -> Result<Scope<'static>> {
+    let mut scope = Scope::new();
+    let values = rhai::serde::to_dynamic(variables.to_json())
+        .map_err(|e| Error::new("Failed to convert variables for script.").with_inner_error(&e))?;
+    for (name, value) in values.cast::<Map>() {
+        if is_identifier(&name) {
+            scope.push_constant_dynamic(name.to_string(), value);
+        }
+    }
+    return Ok(scope);
+}
+
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    return chars.next().is_some_and(|char| char.is_ascii_alphabetic() || char == '_')
+        && chars.all(|char| char.is_ascii_alphanumeric() || char == '_');
+}
+
+/// An engine with the path helpers on `filesystem`, paths are relative to `directory` and may start
+/// with `~`. With `simulate`, the engine also gets the actions, which only log what they would do
+/// when simulating.
+fn engine(filesystem: &Arc<dyn Filesystem>, directory: &Path, variables: &Variables, simulate: Option<bool>) -> Engine {
+    let mut engine = Engine::new();
+    engine.set_max_operations(MAX_OPERATIONS);
+    engine.disable_symbol("eval");
+    engine.on_print(|text| info!("{}", text));
+    engine.on_debug(|text, _, _| debug!("{}", text));
+
+    let base = directory.to_path_buf();
+    let resolve = move |path: &str| -> std::result::Result<PathBuf, Box<EvalAltResult>> {
+        return Path::new(path)
+            .absolutize_in(&base)
+            .map_err(|e| format!("Invalid path `{}`. {}", path, e).into());
+    };
+
+    let (path, files) = (resolve.clone(), filesystem.clone());
+    engine.register_fn("exists", move |name: &str| path(name).map(|path| files.actually_exists(&path)));
+    let (path, files) = (resolve.clone(), filesystem.clone());
+    engine.register_fn("is_dir", move |name: &str| path(name).map(|path| files.is_dir(&path)));
+    let (path, files) = (resolve.clone(), filesystem.clone());
+    engine.register_fn("is_link", move |name: &str| path(name).map(|path| files.is_symbolic(&path)));
+    engine.register_fn("join", |base: &str, name: &str| Path::new(base).join(name).to_string_lossy().to_string());
+    engine.register_fn("parent", |name: &str| {
+        Path::new(name).parent().map(|parent| parent.to_string_lossy().to_string()).unwrap_or_default()
+    });
+    engine.register_fn("file_name", |name: &str| {
+        Path::new(name).file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default()
+    });
+    let values = variables.clone();
+    engine.register_fn("expand", move |content: &str| -> std::result::Result<String, Box<EvalAltResult>> {
+        return values.expand(content).map_err(|e| e.to_string().into());
+    });
+    let (path, files) = (resolve.clone(), filesystem.clone());
+    engine.register_fn("read", move |name: &str| -> std::result::Result<String, Box<EvalAltResult>> {
+        let path = path(name)?;
+        return files.read_to_string(&path).map_err(|e| format!("Failed to read `{}`. {}", name, e).into());
+    });
+
+    let simulate = match simulate {
+        Some(simulate) => simulate,
+        None => return engine,
+    };
+    let (path, files) = (resolve.clone(), filesystem.clone());
+    engine.register_fn("write", move |name: &str, content: &str| -> std::result::Result<(), Box<EvalAltResult>> {
+        let path = path(name)?;
+        info!("Write file: {}.", path.to_str().unwrap());
+        if simulate {
+            return Ok(());
+        }
+        return files.write(&path, content.as_bytes()).map_err(|e| format!("Failed to write `{}`. {}", name, e).into());
+    });
+    let (path, files) = (resolve, filesystem.clone());
+    engine.register_fn("mkdir", move |name: &str| -> std::result::Result<(), Box<EvalAltResult>> {
+        let path = path(name)?;
+        info!("Create directory: {}.", path.to_str().unwrap());
+        if simulate {
+            return Ok(());
+        }
+        return files.mkdir(&path).map_err(|e| format!("Failed to create `{}`. {}", name, e).into());
+    });
+    return engine;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::facts::Facts;
+    use crate::filesystem::MemoryFilesystem;
+
+    #[test]
+    fn paths_expand_home_and_use_the_filesystem() {
+        let memory = Arc::new(MemoryFilesystem::new());
+        memory.add_file("/dotfiles/ssh.rhai", b"mkdir(\"~/.ssh/sockets\"); write(\"out\", read(\"in\"));").unwrap();
+        memory.add_file("/dotfiles/in", b"content").unwrap();
+        let filesystem: Arc<dyn Filesystem> = memory.clone();
+        let workspace = Path::new("/dotfiles");
+        let script = Script::load(filesystem.as_ref(), workspace, "ssh.rhai").unwrap();
+        let variables = Variables::new(Facts::new());
+        script.run(&filesystem, workspace, &variables, &[], false).unwrap();
+        let home = PathBuf::from(shellexpand::tilde("~").as_ref());
+        assert!(memory.is_dir(&home.join(".ssh/sockets")));
+        assert_eq!(memory.read(Path::new("/dotfiles/out")).unwrap(), b"content");
+    }
+
+    #[test]
+    fn simulate_writes_nothing() {
+        let memory = Arc::new(MemoryFilesystem::new());
+        let filesystem: Arc<dyn Filesystem> = memory.clone();
+        let script = Script::load(filesystem.as_ref(), Path::new("/dotfiles"), "mkdir(\"cache\"); write(\"out\", \"x\")").unwrap();
+        script.run(&filesystem, Path::new("/dotfiles"), &Variables::new(Facts::new()), &[], true).unwrap();
+        assert!(!memory.actually_exists(Path::new("/dotfiles/cache")));
+        assert!(!memory.actually_exists(Path::new("/dotfiles/out")));
+    }
+}
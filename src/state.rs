@@ -0,0 +1,122 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+use crate::error::{Error, Result};
+use crate::filesystem::Filesystem;
+use crate::path_extension::PathExtension;
+
+/// What idot knows about the files it deployed from one workspace on this machine.
+///
+/// The state is stored outside the workspace, under `$XDG_STATE_HOME/idot`, because it
+/// describes the local machine rather than the dotfiles.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct State {
+    #[serde(skip)]
+    path: PathBuf,
+    #[serde(default)]
+    pub entries: BTreeMap<String, StateEntry>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct StateEntry {
+    pub mode: String,
+    pub target: String,
+    /// Content hash of the deployed file when idot last wrote it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hash: Option<String>,
+    /// Keys written by `merge` mode.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub keys: Option<Vec<MergedKey>>,
+    /// Files and directories written by `extract` mode, relative to the link path.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub manifest: Option<Vec<ManifestEntry>>,
+    /// Hash of the command and inputs that `generate` mode ran with.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_key: Option<String>,
+    /// Reverse of the patch that `patch` mode applied.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reverse: Option<String>,
+}
+
+/// A key that `merge` mode added to a document, or replaced if it had a previous value.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MergedKey {
+    pub path: Vec<String>,
+    /// The value idot wrote.
+    pub value: Value,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub previous: Option<Value>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    pub path: String,
+    /// Content hash of a file, `None` for a directory.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hash: Option<String>,
+}
+
+impl State {
+    pub fn load<P: AsRef<Path>>(filesystem: &dyn Filesystem, workspace: P) -> Result<State> {
+        let workspace = workspace.as_ref();
+        let path = state_directory()?.join(format!("{}.json", &hash_bytes(workspace.to_string_lossy().as_bytes())[..16]));
+        if !filesystem.exists(&path) {
+            return Ok(State {
+                path,
+                entries: BTreeMap::new(),
+            });
+        }
+        let content = filesystem
+            .read_to_string(&path)
+            .map_err(|e| Error::new("Failed to load state file.").with_inner_error(&e))?;
+        let mut state = serde_json::from_str::<State>(&content)
+            .map_err(|e| Error::new("Failed to convert state file.").with_inner_error(&e))?;
+        state.path = path;
+        return Ok(state);
+    }
+
+    pub fn save(&self, filesystem: &dyn Filesystem) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            filesystem
+                .mkdir(parent)
+                .map_err(|e| Error::new("Failed to create state directory.").with_inner_error(&e))?;
+        }
+        let content = serde_json::to_string_pretty(self)
+            .map_err(|e| Error::new("Failed to serialize state.").with_inner_error(&e))?;
+        return filesystem
+            .write(&self.path, content.as_bytes())
+            .map_err(|e| Error::new("Failed to save state file.").with_inner_error(&e));
+    }
+
+    pub fn get<P: AsRef<Path>>(&self, path: P) -> Option<&StateEntry> {
+        return self.entries.get(path.as_ref().to_str().unwrap());
+    }
+
+    pub fn set<P: AsRef<Path>>(&mut self, path: P, entry: StateEntry) {
+        self.entries.insert(path.as_ref().to_str().unwrap().to_string(), entry);
+    }
+
+    pub fn remove<P: AsRef<Path>>(&mut self, path: P) {
+        self.entries.remove(path.as_ref().to_str().unwrap());
+    }
+}
+
+fn state_directory() -> Result<PathBuf> {
+    let directory = match env::var("XDG_STATE_HOME") {
+        Ok(directory) if !directory.is_empty() => PathBuf::from(directory),
+        _ => PathBuf::from("~/.local/state"),
+    };
+    return directory
+        .join("idot")
+        .absolutize()
+        .map_err(|e| Error::new("Invalid state directory.").with_inner_error(&e));
+}
+
+pub fn hash_bytes(content: &[u8]) -> String {
+    return format!("{:x}", Sha256::digest(content));
+}
@@ -0,0 +1,348 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use log::debug;
+
+use crate::command;
+use crate::condition;
+use crate::configuration::GroupConfiguration;
+use crate::error::{Error, Result};
+use crate::filesystem::Filesystem;
+use crate::hook;
+use crate::path_extension::PathExtension;
+use crate::report::{Entry, Report};
+use crate::state::{hash_bytes, State, StateEntry};
+use crate::trust::TrustedCommand;
+use crate::variables::Variables;
+
+pub const MODE: &str = "task";
+
+/// A bootstrap step from the `tasks` of the configuration.
+pub struct Task {
+    pub name: String,
+    /// Where the task is written in the configuration, e.g. `groups.vim.tasks.undo-directory`.
+    pub location: String,
+    pub command: String,
+    /// The task is done when this path exists.
+    pub creates: Option<PathBuf>,
+    /// The task is done when this command succeeds.
+    pub unless: Option<String>,
+    /// The task is skipped unless this command succeeds.
+    pub onlyif: Option<String>,
+    /// Tasks that must run before this one.
+    pub after: Vec<String>,
+    pub workspace: PathBuf,
+    pub directory: PathBuf,
+    pub timeout: Option<Duration>,
+    pub not_applicable: Option<String>,
+    /// The variables of its group, the command and guards get them as environment variables.
+    pub variables: Variables,
+    pub filesystem: Arc<dyn Filesystem>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TaskStatus {
+    Pending,
+    Done(String),
+    Skipped(String),
+}
+
+impl Task {
+    fn key(&self) -> PathBuf {
+        return PathBuf::from(format!("{}:{}", MODE, self.name));
+    }
+
+    fn environment(&self, simulate: bool) -> Vec<(String, String)> {
+        let mut environment = self.variables.environment();
+        environment.push(("IDOT_TASK".to_string(), self.name.clone()));
+        environment.push(("IDOT_WORKSPACE".to_string(), self.workspace.to_str().unwrap().to_string()));
+        environment.push(("IDOT_SIMULATE".to_string(), hook::flag(simulate)));
+        return environment;
+    }
+
+    /// Whether the command and the guards of the task are allowed. The guards of an untrusted
+    /// task must not run, not even to check its status.
+    pub fn trusted(&self, untrusted: &[TrustedCommand]) -> bool {
+        let prefix = format!("{}.", self.location);
+        return !untrusted.iter().any(|command| command.location.starts_with(&prefix));
+    }
+
+    /// Whether a guard command succeeds, its output is discarded.
+    fn check(&self, guard: &str) -> bool {
+        let succeeded = command::output(guard, &self.directory, &self.variables.environment()).is_ok();
+        debug!("Guard of task `{}`: `{}` {}.", &self.name, guard, if succeeded { "succeeded" } else { "failed" });
+        return succeeded;
+    }
+
+    pub fn status(&self, state: &State) -> TaskStatus {
+        if let Some(ref creates) = self.creates {
+            if self.filesystem.actually_exists(creates) {
+                return TaskStatus::Done(format!("`{}` exists", creates.to_str().unwrap()));
+            }
+        }
+        if let Some(ref unless) = self.unless {
+            if self.check(unless) {
+                return TaskStatus::Done(format!("`{}` succeeded", unless));
+            }
+        }
+        if let Some(ref onlyif) = self.onlyif {
+            if !self.check(onlyif) {
+                return TaskStatus::Skipped(format!("`{}` failed", onlyif));
+            }
+        }
+        // Without a guard a task runs once, and again when its command changes.
+        if self.creates.is_none() && self.unless.is_none() {
+            let ran = state
+                .get(self.key())
+                .filter(|entry| entry.mode == MODE)
+                .is_some_and(|entry| entry.hash == Some(hash_bytes(self.command.as_bytes())));
+            if ran {
+                return TaskStatus::Done("ran before".to_string());
+            }
+        }
+        return TaskStatus::Pending;
+    }
+}
+
+/// Every task of the configuration and its groups, ordered so that each task comes after the
+/// tasks it depends on.
+pub fn tasks(
+    filesystem: &Arc<dyn Filesystem>,
+    workspace: &Path,
+    configuration: &GroupConfiguration,
+    variables: &Variables,
+) -> Result<Vec<Task>> {
+    let mut result = vec![];
+    collect_tasks(filesystem, workspace, configuration, "", variables, None, &mut result)?;
+    return order(result);
+}
+
+fn collect_tasks(
+    filesystem: &Arc<dyn Filesystem>,
+    workspace: &Path,
+    group: &GroupConfiguration,
+    prefix: &str,
+    variables: &Variables,
+    not_applicable: Option<String>,
+    result: &mut Vec<Task>,
+) -> Result<()> {
+    let variables = variables.clone().with_values(&group.variables)?.with_script(filesystem, workspace, &group.script)?;
+    let not_applicable = match not_applicable {
+        Some(clause) => Some(clause),
+        None => condition::failing_clause(filesystem, workspace, &group.when, &variables)?,
+    };
+    for (name, task) in group.tasks.iter().flatten() {
+        if result.iter().any(|other: &Task| other.name == *name) {
+            return Err(Error::new(&format!("Task `{}` is defined more than once.", name)));
+        }
+        let resolve = |path: &str| -> Result<PathBuf> {
+            return Path::new(&variables.expand(path)?)
+                .absolutize_in(workspace)
+                .map_err(|e| Error::new("Invalid task path.").with_inner_error(&e));
+        };
+        result.push(Task {
+            name: name.clone(),
+            location: format!("{}tasks.{}", prefix, name),
+            command: task.command.clone(),
+            creates: task.creates.as_deref().map(resolve).transpose()?,
+            unless: task.unless.clone(),
+            onlyif: task.onlyif.clone(),
+            after: task.after.clone().unwrap_or_default(),
+            workspace: workspace.to_path_buf(),
+            directory: match task.cwd {
+                Some(ref cwd) => resolve(cwd)?,
+                None => workspace.to_path_buf(),
+            },
+            timeout: task.timeout.map(Duration::from_secs),
+            not_applicable: match not_applicable {
+                Some(ref clause) => Some(clause.clone()),
+                None => condition::failing_clause(filesystem, workspace, &task.when, &variables)?,
+            },
+            variables: variables.clone(),
+            filesystem: filesystem.clone(),
+        });
+    }
+    for (name, group) in group.groups.iter().flatten() {
+        collect_tasks(filesystem, workspace, group, &format!("{}groups.{}.", prefix, name), &variables, not_applicable.clone(), result)?;
+    }
+    return Ok(());
+}
+
+/// Sort tasks after their dependencies, keeping the configuration order otherwise.
+fn order(tasks: Vec<Task>) -> Result<Vec<Task>> {
+    for task in &tasks {
+        for dependency in &task.after {
+            if !tasks.iter().any(|other| other.name == *dependency) {
+                return Err(Error::new(&format!("Unknown task `{}` in dependencies of `{}`.", dependency, &task.name)));
+            }
+        }
+    }
+    let mut remaining: Vec<Task> = tasks;
+    let mut sorted: Vec<Task> = vec![];
+    while !remaining.is_empty() {
+        let next = remaining
+            .iter()
+            .position(|task| task.after.iter().all(|dependency| sorted.iter().any(|done| done.name == *dependency)));
+        match next {
+            Some(index) => sorted.push(remaining.remove(index)),
+            None => {
+                let cycle: Vec<&str> = remaining.iter().map(|task| task.name.as_str()).collect();
+                return Err(Error::new(&format!("Dependency cycle between tasks `{}`.", cycle.join("`, `"))));
+            }
+        }
+    }
+    return Ok(sorted);
+}
+
+/// Run the pending tasks, or only `name` and the tasks it depends on. A task whose dependency
+/// failed is not run. Untrusted tasks fail, with `simulate` they are reported as pending without
+/// running their guards.
+pub fn run(
+    tasks: &[Task],
+    name: Option<&str>,
+    state: &mut State,
+    untrusted: &[TrustedCommand],
+    simulate: bool,
+    report: &mut Report,
+) -> Result<()> {
+    let mut selected: Vec<&str> = match name {
+        Some(name) => {
+            if !tasks.iter().any(|task| task.name == name) {
+                return Err(Error::new(&format!("Unknown task `{}`.", name)));
+            }
+            vec![name]
+        }
+        None => tasks.iter().map(|task| task.name.as_str()).collect(),
+    };
+    // Tasks come after their dependencies, so walking backwards finds every dependency.
+    for task in tasks.iter().rev() {
+        if selected.contains(&task.name.as_str()) {
+            for dependency in &task.after {
+                if !selected.contains(&dependency.as_str()) {
+                    selected.push(dependency);
+                }
+            }
+        }
+    }
+    let mut failed: Vec<&str> = vec![];
+    for task in tasks.iter().filter(|task| selected.contains(&task.name.as_str())) {
+        if let Some(ref clause) = task.not_applicable {
+            debug!("Skip not applicable task: `{}`, `{}` is false.", &task.name, clause);
+            continue;
+        }
+        if let Some(dependency) = task.after.iter().find(|dependency| failed.contains(&dependency.as_str())) {
+            report.fail("run task".to_string(), &task.name, &format!("Its dependency `{}` failed.", dependency));
+            failed.push(&task.name);
+            continue;
+        }
+        if !task.trusted(untrusted) {
+            if simulate {
+                report.push(Entry::TaskUntrusted {
+                    name: task.name.clone(),
+                    command: task.command.clone(),
+                });
+            } else {
+                report.fail("run task".to_string(), &task.name, &"Its commands are not trusted, allow them with `idot allow`.");
+                failed.push(&task.name);
+            }
+            continue;
+        }
+        match task.status(state) {
+            TaskStatus::Done(reason) => {
+                debug!("Task is done: `{}`, {}.", &task.name, reason);
+                continue;
+            }
+            TaskStatus::Skipped(reason) => {
+                debug!("Skip task: `{}`, {}.", &task.name, reason);
+                continue;
+            }
+            TaskStatus::Pending => {}
+        }
+        report.push(Entry::TaskRun {
+            name: task.name.clone(),
+            command: task.command.clone(),
+        });
+        if simulate {
+            continue;
+        }
+        match command::run(&task.command, &task.directory, &task.environment(simulate), task.timeout) {
+            Ok(()) => state.set(
+                task.key(),
+                StateEntry {
+                    mode: MODE.to_string(),
+                    target: task.command.clone(),
+                    hash: Some(hash_bytes(task.command.as_bytes())),
+                    ..Default::default()
+                },
+            ),
+            Err(error) => {
+                report.fail("run task".to_string(), &task.name, &error);
+                failed.push(&task.name);
+            }
+        }
+    }
+    return Ok(());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::facts::Facts;
+    use crate::filesystem::MemoryFilesystem;
+
+    fn tasks_of(filesystem: &Arc<MemoryFilesystem>, configuration: &str) -> Vec<Task> {
+        let filesystem: Arc<dyn Filesystem> = filesystem.clone();
+        let configuration = serde_yaml::from_str(configuration).unwrap();
+        return tasks(&filesystem, Path::new("/dotfiles"), &configuration, &Variables::new(Facts::new())).unwrap();
+    }
+
+    #[test]
+    fn creates_expands_home() {
+        let filesystem = Arc::new(MemoryFilesystem::new());
+        let tasks = tasks_of(&filesystem, "tasks:\n  tpm:\n    command: git clone tpm\n    creates: ~/.tmux/plugins/tpm\n    cwd: ~/.tmux\n");
+        let home = PathBuf::from(shellexpand::tilde("~").as_ref());
+        assert_eq!(tasks[0].creates, Some(home.join(".tmux/plugins/tpm")));
+        assert_eq!(tasks[0].directory, home.join(".tmux"));
+        assert_eq!(tasks[0].status(&State::default()), TaskStatus::Pending);
+        filesystem.add_dir(home.join(".tmux/plugins/tpm")).unwrap();
+        assert!(matches!(tasks[0].status(&State::default()), TaskStatus::Done(_)));
+    }
+
+    #[test]
+    fn guards_get_variables() {
+        let filesystem = Arc::new(MemoryFilesystem::new());
+        let tasks = tasks_of(
+            &filesystem,
+            "variables:\n  shell: zsh\ntasks:\n  shell:\n    command: chsh\n    cwd: /\n    unless: test \"$shell\" = zsh\n",
+        );
+        assert_eq!(tasks[0].status(&State::default()), TaskStatus::Done("`test \"$shell\" = zsh` succeeded".to_string()));
+    }
+
+    #[test]
+    fn tasks_come_after_their_dependencies() {
+        let filesystem = Arc::new(MemoryFilesystem::new());
+        let tasks = tasks_of(
+            &filesystem,
+            "tasks:\n  plugins:\n    command: vim +PlugInstall\n    after: [plug]\n  plug:\n    command: curl plug.vim\n  fonts:\n    command: fc-cache\n",
+        );
+        let names: Vec<&str> = tasks.iter().map(|task| task.name.as_str()).collect();
+        assert_eq!(names, vec!["plug", "plugins", "fonts"]);
+    }
+
+    #[test]
+    fn task_dependency_cycle_fails() {
+        let filesystem: Arc<dyn Filesystem> = Arc::new(MemoryFilesystem::new());
+        let configuration = serde_yaml::from_str("tasks:\n  a:\n    command: a\n    after: [b]\n  b:\n    command: b\n    after: [a]\n").unwrap();
+        let error = tasks(&filesystem, Path::new("/dotfiles"), &configuration, &Variables::new(Facts::new())).err().unwrap();
+        assert_eq!(error.to_string(), "Dependency cycle between tasks `a`, `b`.");
+    }
+
+    #[test]
+    fn creates_is_relative_to_workspace() {
+        let filesystem = Arc::new(MemoryFilesystem::new());
+        let tasks = tasks_of(&filesystem, "tasks:\n  build:\n    command: make\n    creates: build/out\n");
+        assert_eq!(tasks[0].creates, Some(PathBuf::from("/dotfiles/build/out")));
+        assert_eq!(tasks[0].directory, PathBuf::from("/dotfiles"));
+    }
+}
@@ -0,0 +1,319 @@
+use std::env;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use crate::configuration::{GroupConfiguration, HooksConfiguration};
+use crate::error::{Error, Result};
+use crate::filesystem::Filesystem;
+use crate::path_extension::PathExtension;
+use crate::script;
+use crate::state::hash_bytes;
+use crate::variables::Variables;
+
+/// The commands of one workspace that the user allowed to run.
+///
+/// Like the state, the trust store lives outside the workspace, under `$XDG_DATA_HOME/idot/trust`,
+/// so a workspace can't trust itself.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct TrustStore {
+    #[serde(skip)]
+    path: PathBuf,
+    pub workspace: String,
+    #[serde(default)]
+    pub commands: Vec<TrustedCommand>,
+}
+
+/// A command-bearing section of the configuration, e.g. `links.~/.tmux.conf.hooks.post_create`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TrustedCommand {
+    pub location: String,
+    pub hash: String,
+    pub command: String,
+    /// The variables and facts the command references, as `name=value`. Commands get them from
+    /// their environment, so they are trusted with the command.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub values: Vec<String>,
+}
+
+impl TrustedCommand {
+    fn new(location: String, command: &str, variables: &Variables) -> Self {
+        let references = variables.references(command);
+        let values: Vec<String> = variables
+            .environment()
+            .into_iter()
+            .filter(|(name, _)| references.contains(name))
+            .map(|(name, value)| format!("{}={}", name, value))
+            .collect();
+        let mut content = command.to_string();
+        for value in &values {
+            content.push('\n');
+            content.push_str(value);
+        }
+        return TrustedCommand {
+            location,
+            hash: hash_bytes(content.as_bytes()),
+            command: command.to_string(),
+            values,
+        };
+    }
+
+    /// A hook script, a `.rhai` file is trusted by the hash of its content.
+    fn script(filesystem: &dyn Filesystem, workspace: &Path, location: String, script: &str) -> Self {
+        let content = if script::is_file(script) {
+            filesystem.read(&workspace.join(script)).unwrap_or_default()
+        } else {
+            script.as_bytes().to_vec()
+        };
+        return TrustedCommand {
+            location,
+            hash: hash_bytes(&content),
+            command: script.to_string(),
+            values: vec![],
+        };
+    }
+}
+
+impl fmt::Display for TrustedCommand {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.values.is_empty() {
+            return write!(f, "{}", self.command);
+        }
+        return write!(f, "{} with {}", self.command, self.values.join(", "));
+    }
+}
+
+impl TrustStore {
+    pub fn load<P: AsRef<Path>>(filesystem: &dyn Filesystem, workspace: P) -> Result<TrustStore> {
+        let workspace = workspace.as_ref().to_string_lossy().to_string();
+        let path = trust_directory()?.join(format!("{}.json", &hash_bytes(workspace.as_bytes())[..16]));
+        if !filesystem.exists(&path) {
+            return Ok(TrustStore {
+                path,
+                workspace,
+                commands: vec![],
+            });
+        }
+        let content = filesystem
+            .read_to_string(&path)
+            .map_err(|e| Error::new("Failed to load trust store.").with_inner_error(&e))?;
+        let mut store = serde_json::from_str::<TrustStore>(&content)
+            .map_err(|e| Error::new("Failed to convert trust store.").with_inner_error(&e))?;
+        store.path = path;
+        return Ok(store);
+    }
+
+    pub fn save(&self, filesystem: &dyn Filesystem) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            filesystem
+                .mkdir(parent)
+                .map_err(|e| Error::new("Failed to create trust store directory.").with_inner_error(&e))?;
+        }
+        let content = serde_json::to_string_pretty(self)
+            .map_err(|e| Error::new("Failed to serialize trust store.").with_inner_error(&e))?;
+        return filesystem
+            .write(&self.path, content.as_bytes())
+            .map_err(|e| Error::new("Failed to save trust store.").with_inner_error(&e));
+    }
+
+    /// Commands that are new or changed since they were allowed.
+    pub fn untrusted<'a>(&self, commands: &'a [TrustedCommand]) -> Vec<&'a TrustedCommand> {
+        return commands
+            .iter()
+            .filter(|command| {
+                !self
+                    .commands
+                    .iter()
+                    .any(|trusted| trusted.location == command.location && trusted.hash == command.hash)
+            })
+            .collect();
+    }
+}
+
+/// Every command in the configuration, with where it is written. `variables` are those of the
+/// top-level configuration.
+pub fn commands(
+    filesystem: &Arc<dyn Filesystem>,
+    workspace: &Path,
+    configuration: &GroupConfiguration,
+    variables: &Variables,
+) -> Result<Vec<TrustedCommand>> {
+    let mut result = vec![];
+    collect_commands(filesystem, workspace, configuration, "", variables, &mut result)?;
+    return Ok(result);
+}
+
+fn collect_commands(
+    filesystem: &Arc<dyn Filesystem>,
+    workspace: &Path,
+    group: &GroupConfiguration,
+    prefix: &str,
+    variables: &Variables,
+    result: &mut Vec<TrustedCommand>,
+) -> Result<()> {
+    // The top-level variables are already resolved.
+    let variables = match prefix {
+        "" => variables.clone(),
+        _ => variables.clone().with_values(&group.variables)?.with_script(filesystem, workspace, &group.script)?,
+    };
+    collect_hooks(filesystem.as_ref(), workspace, &group.hooks, &format!("{}hooks", prefix), &variables, result);
+    for (link, configuration) in group.links.iter().flatten() {
+        let location = format!("{}links.{}", prefix, link);
+        if let Some(ref command) = configuration.command {
+            result.push(TrustedCommand::new(format!("{}.command", location), command, &variables));
+        }
+        collect_hooks(filesystem.as_ref(), workspace, &configuration.hooks, &format!("{}.hooks", location), &variables, result);
+    }
+    for (name, task) in group.tasks.iter().flatten() {
+        let location = format!("{}tasks.{}", prefix, name);
+        result.push(TrustedCommand::new(format!("{}.command", location), &task.command, &variables));
+        if let Some(ref unless) = task.unless {
+            result.push(TrustedCommand::new(format!("{}.unless", location), unless, &variables));
+        }
+        if let Some(ref onlyif) = task.onlyif {
+            result.push(TrustedCommand::new(format!("{}.onlyif", location), onlyif, &variables));
+        }
+    }
+    for (name, group) in group.groups.iter().flatten() {
+        collect_commands(filesystem, workspace, group, &format!("{}groups.{}.", prefix, name), &variables, result)?;
+    }
+    return Ok(());
+}
+
+fn collect_hooks(
+    filesystem: &dyn Filesystem,
+    workspace: &Path,
+    hooks: &Option<HooksConfiguration>,
+    location: &str,
+    variables: &Variables,
+    result: &mut Vec<TrustedCommand>,
+) {
+    let hooks = match hooks {
+        Some(hooks) => hooks,
+        None => return,
+    };
+    let events = [
+        ("pre_create", &hooks.pre_create),
+        ("post_create", &hooks.post_create),
+        ("pre_delete", &hooks.pre_delete),
+        ("post_delete", &hooks.post_delete),
+    ];
+    for (event, hooks) in events {
+        for (index, hook) in hooks.iter().flatten().enumerate() {
+            let location = format!("{}.{}[{}]", location, event, index);
+            if let Some(ref command) = hook.command {
+                result.push(TrustedCommand::new(location.clone(), command, variables));
+            }
+            if let Some(ref script) = hook.script {
+                result.push(TrustedCommand::script(filesystem, workspace, location, script));
+            }
+        }
+    }
+}
+
+/// The commands of the configuration the user didn't allow, or changed since.
+pub fn untrusted(
+    filesystem: &Arc<dyn Filesystem>,
+    workspace: &Path,
+    configuration: &GroupConfiguration,
+    variables: &Variables,
+) -> Result<Vec<TrustedCommand>> {
+    let store = TrustStore::load(filesystem.as_ref(), workspace)?;
+    let commands = commands(filesystem, workspace, configuration, variables)?;
+    return Ok(store.untrusted(&commands).into_iter().cloned().collect());
+}
+
+/// A command that is new, changed or gone since the commands were last allowed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CommandChange {
+    pub location: String,
+    pub previous: Option<String>,
+    pub current: Option<String>,
+}
+
+/// Trust the commands of the configuration. Returns how they changed since they were last
+/// allowed, nothing when they are all trusted already.
+pub fn allow(
+    filesystem: &Arc<dyn Filesystem>,
+    workspace: &Path,
+    configuration: &GroupConfiguration,
+    variables: &Variables,
+) -> Result<Vec<CommandChange>> {
+    let mut store = TrustStore::load(filesystem.as_ref(), workspace)?;
+    let commands = commands(filesystem, workspace, configuration, variables)?;
+    let mut changes = vec![];
+    for previous in &store.commands {
+        match commands.iter().find(|command| command.location == previous.location) {
+            Some(command) if command.hash == previous.hash => {}
+            current => changes.push(CommandChange {
+                location: previous.location.clone(),
+                previous: Some(previous.to_string()),
+                current: current.map(|command| command.to_string()),
+            }),
+        }
+    }
+    for command in &commands {
+        if !store.commands.iter().any(|previous| previous.location == command.location) {
+            changes.push(CommandChange {
+                location: command.location.clone(),
+                previous: None,
+                current: Some(command.to_string()),
+            });
+        }
+    }
+    if changes.is_empty() {
+        return Ok(changes);
+    }
+    store.commands = commands;
+    store.save(filesystem.as_ref())?;
+    return Ok(changes);
+}
+
+fn trust_directory() -> Result<PathBuf> {
+    let directory = match env::var("XDG_DATA_HOME") {
+        Ok(directory) if !directory.is_empty() => PathBuf::from(directory),
+        _ => PathBuf::from("~/.local/share"),
+    };
+    return directory
+        .join("idot")
+        .join("trust")
+        .absolutize()
+        .map_err(|e| Error::new("Invalid trust store directory.").with_inner_error(&e));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::facts::Facts;
+    use crate::filesystem::MemoryFilesystem;
+
+    fn hashes(configuration: &str, editor: &str) -> Vec<String> {
+        let filesystem: Arc<dyn Filesystem> = Arc::new(MemoryFilesystem::new());
+        let configuration: GroupConfiguration = serde_yaml::from_str(configuration).unwrap();
+        let variables = Variables::new(Facts::new())
+            .with_values(&serde_yaml::from_str(&format!("editor: {}\nshell: zsh", editor)).unwrap())
+            .unwrap();
+        let commands = commands(&filesystem, Path::new("/dotfiles"), &configuration, &variables).unwrap();
+        return commands.into_iter().map(|command| command.hash).collect();
+    }
+
+    #[test]
+    fn hash_covers_referenced_values() {
+        let configuration = "tasks:\n  editor:\n    command: echo \"$editor\"\n  shell:\n    command: chsh -s \"${shell}\"\n";
+        let (vim, emacs) = (hashes(configuration, "vim"), hashes(configuration, "emacs"));
+        assert_ne!(vim[0], emacs[0]);
+        assert_eq!(vim[1], emacs[1]);
+    }
+
+    #[test]
+    fn group_variables_are_referenced_values() {
+        let configuration = "groups:\n  vim:\n    variables:\n      editor: nvim\n    tasks:\n      editor:\n        command: echo $editor\n";
+        assert_eq!(hashes(configuration, "vim"), hashes(configuration, "emacs"));
+        let filesystem: Arc<dyn Filesystem> = Arc::new(MemoryFilesystem::new());
+        let configuration: GroupConfiguration = serde_yaml::from_str(configuration).unwrap();
+        let commands = commands(&filesystem, Path::new("/dotfiles"), &configuration, &Variables::new(Facts::new())).unwrap();
+        assert_eq!(commands[0].to_string(), "echo $editor with editor=nvim");
+    }
+}
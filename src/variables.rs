@@ -0,0 +1,111 @@
+use std::collections::HashMap;
+use std::env;
+use std::path::Path;
+use std::sync::Arc;
+
+use serde_json::{json, Map, Value};
+use shellexpand::env_with_context;
+
+use crate::configuration::OrderedMap;
+use crate::error::{Error, Result};
+use crate::facts::Facts;
+use crate::filesystem::Filesystem;
+use crate::script::Script;
+
+/// Values that can be referenced as `$name` or `${name}` in the configuration.
+///
+/// Lookups prefer configuration variables, then facts, then the environment.
+#[derive(Debug, Clone)]
+pub struct Variables {
+    pub facts: Facts,
+    values: HashMap<String, String>,
+}
+
+impl Variables {
+    pub fn new(facts: Facts) -> Self {
+        return Variables {
+            facts,
+            values: HashMap::new(),
+        };
+    }
+
+    pub fn with_values(mut self, values: &Option<OrderedMap<String>>) -> Result<Self> {
+        if let Some(values) = values {
+            for (name, value) in values {
+                let value = self.expand(value)?;
+                self.values.insert(name.clone(), value);
+            }
+        }
+        return Ok(self);
+    }
+
+    /// Add the variables computed by a script, see `Script::variables`.
+    pub fn with_script(mut self, filesystem: &Arc<dyn Filesystem>, workspace: &Path, script: &Option<String>) -> Result<Self> {
+        if let Some(script) = script {
+            for (name, value) in Script::load(filesystem.as_ref(), workspace, script)?.variables(filesystem, workspace, &self)? {
+                self.values.insert(name, value);
+            }
+        }
+        return Ok(self);
+    }
+
+    pub fn get(&self, name: &str) -> Option<String> {
+        return self
+            .values
+            .get(name)
+            .cloned()
+            .or_else(|| self.facts.get(name).map(|value| value.to_string()))
+            .or_else(|| env::var(name).ok());
+    }
+
+    /// Variables and facts at the top level, plus `facts` and `env` objects.
+    pub fn to_json(&self) -> Value {
+        let mut values = Map::new();
+        for (name, value) in self.facts.iter() {
+            values.insert(name.clone(), Value::String(value.clone()));
+        }
+        for (name, value) in &self.values {
+            values.insert(name.clone(), Value::String(value.clone()));
+        }
+        values.insert("facts".to_string(), json!(self.facts));
+        let environment: Map<String, Value> = env::vars().map(|(name, value)| (name, Value::String(value))).collect();
+        values.insert("env".to_string(), Value::Object(environment));
+        return Value::Object(values);
+    }
+
+    /// Variables and facts as environment variables of a command, so it can reference them
+    /// without idot expanding it. Names that aren't shell identifiers are left out.
+    pub fn environment(&self) -> Vec<(String, String)> {
+        let mut environment: Vec<(String, String)> = vec![];
+        let names = self.facts.iter().map(|(name, _)| name).chain(self.values.keys());
+        for name in names {
+            let identifier = name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+                && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+            if identifier && !environment.iter().any(|(existing, _)| existing == name) {
+                environment.push((name.clone(), self.get(name).unwrap_or_default()));
+            }
+        }
+        return environment;
+    }
+
+    /// Names of the variables referenced by `content`.
+    pub fn references(&self, content: &str) -> Vec<String> {
+        let mut names: Vec<String> = vec![];
+        let _ = env_with_context(content, |name| -> std::result::Result<Option<String>, Error> {
+            if !names.iter().any(|existing| existing == name) {
+                names.push(name.to_string());
+            }
+            return Ok(None);
+        });
+        return names;
+    }
+
+    pub fn expand(&self, content: &str) -> Result<String> {
+        return env_with_context(content, |name| match self.get(name) {
+            Some(value) => Ok(Some(value)),
+            None => Err(Error::new(&format!("Undefined variable `{}`.", name))),
+        })
+        .map(|value| value.to_string())
+        .map_err(|e| e.cause);
+    }
+}